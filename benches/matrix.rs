@@ -0,0 +1,25 @@
+use std::f64::consts::PI;
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use raytracer::matrix::Matrix;
+use raytracer::vec4::Vec4;
+
+fn matrix(c: &mut Criterion) {
+    let a = Matrix::translation(1.0, -2.0, 3.0)
+        * Matrix::rotation_y(PI / 5.0)
+        * Matrix::scaling(2.0, 0.5, 1.5);
+    let b = Matrix::rotation_x(PI / 3.0) * Matrix::translation(-4.0, 0.5, 2.0);
+    let p = Vec4::point(0.3, -1.2, 4.0);
+
+    c.bench_function("multiply 4x4", |bench| {
+        bench.iter(|| black_box(a) * black_box(b))
+    });
+    c.bench_function("inverse 4x4", |bench| bench.iter(|| black_box(a).inverse()));
+    c.bench_function("transform point", |bench| {
+        bench.iter(|| black_box(a) * black_box(p))
+    });
+}
+
+criterion_group!(benches, matrix);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use raytracer::{
+    Sphere,
+    camera::Camera,
+    color::Color,
+    light::PointLight,
+    material::Material,
+    matrix::Matrix,
+    patterns::{Pattern, checker::Checker},
+    progress::Silent,
+    sampling::Stratified,
+    shapes::{Shape, cube::Cube, plane::Plane},
+    vec4::Vec4,
+    world::World,
+};
+
+// the scene of `main` without the teapot, at a fraction of its resolution
+fn scene() -> (Camera, World) {
+    let mut floor = Plane::new();
+    let mut mat = Material::default();
+    mat.reflective = 0.8;
+    let mut pat = Checker::new(Color::light_gray(), Color::dark_gray());
+    pat.set_transformation(Matrix::scaling(5.0, 5.0, 5.0));
+    mat.set_pattern(pat);
+    floor.set_material(mat);
+
+    let mut world = World::new(PointLight {
+        position: Vec4::point(-10.0, 20.0, -10.0),
+        intensity: Color::white(),
+    });
+
+    let mut s = Cube::new();
+    s.set_transformation(Matrix::translation(-3.0, 1.0, -3.0) * Matrix::rotation_y(PI / 3.0));
+    s.material.reflective = 0.9;
+    s.material.color = Color::red();
+
+    let mut sb = Sphere::new();
+    sb.material.set_color(Color::light_gray());
+    sb.material.reflective = 0.9;
+    sb.material.transparency = 0.9;
+    sb.material.diffuse = 0.1;
+    sb.material.ambient = 0.1;
+    sb.material.refractive_index = 1.5;
+    sb.set_transformation(Matrix::translation(1.0, 2.5, -1.0) * Matrix::scaling(2.5, 2.5, 2.5));
+
+    world.add_shape(Arc::new(floor));
+    world.add_shape(Arc::new(s));
+    world.add_shape(Arc::new(sb));
+
+    let mut camera = Camera::new(160, 80, PI / 3.0, 5, 1);
+    camera.set_view(
+        Vec4::point(0.0, 10.0, -20.0),
+        Vec4::point(0.0, 1.0, 0.0),
+        Vec4::vector(0.0, 1.0, 0.0),
+    );
+    camera.set_sampling(4, Stratified);
+    camera.set_progress(Silent);
+    (camera, world)
+}
+
+fn render(c: &mut Criterion) {
+    let (camera, world) = scene();
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("main scene 160x80", |bench| {
+        bench.iter(|| camera.render(&world))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
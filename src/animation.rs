@@ -0,0 +1,412 @@
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::matrix::SqMatrix;
+use crate::transform::{Decomposed, catmull_rom_weights};
+use crate::vec4::Vec4;
+use crate::world::World;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    /// Catmull-Rom spline, passes through every keyframe with a smooth
+    /// tangent.
+    Spline,
+}
+
+/// Values that can be keyframed.
+pub trait Animatable: Copy {
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+    /// Spline between `p1` and `p2`, with `p0` and `p3` as the neighbouring keys.
+    fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f64) -> Self;
+}
+
+impl Animatable for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+    fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f64) -> Self {
+        let [w0, w1, w2, w3] = catmull_rom_weights(t);
+        p0 * w0 + p1 * w1 + p2 * w2 + p3 * w3
+    }
+}
+
+impl Animatable for Vec4 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self * (1.0 - t) + *other * t
+    }
+    fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f64) -> Self {
+        let [w0, w1, w2, w3] = catmull_rom_weights(t);
+        *p0 * w0 + *p1 * w1 + *p2 * w2 + *p3 * w3
+    }
+}
+
+/// Transforms are split into translation, rotation and scale before they
+/// are interpolated, so rotations don't shear.
+impl Animatable for SqMatrix<4> {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Decomposed::new(self)
+            .lerp(&Decomposed::new(other), t)
+            .matrix()
+    }
+    fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f64) -> Self {
+        let [d0, d1, d2, d3] = [p0, p1, p2, p3].map(Decomposed::new);
+        Decomposed::catmull_rom(&d0, &d1, &d2, &d3, t).matrix()
+    }
+}
+
+/// Keyframes of a single value, sorted by time. Before the first and after
+/// the last key the value holds still.
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    pub keyframes: Vec<(f64, T)>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn new(interpolation: Interpolation) -> Self {
+        Track {
+            keyframes: Vec::new(),
+            interpolation,
+        }
+    }
+    pub fn linear() -> Self {
+        Track::new(Interpolation::Linear)
+    }
+    pub fn spline() -> Self {
+        Track::new(Interpolation::Spline)
+    }
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        let index = self.keyframes.partition_point(|(t, _)| *t <= time);
+        self.keyframes.insert(index, (time, value));
+        self
+    }
+    /// Value at `time`, `None` if the track has no keys.
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let keys = &self.keyframes;
+        let (first, last) = (keys.first()?, keys.last()?);
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        if time >= last.0 {
+            return Some(last.1);
+        }
+        let i = keys.partition_point(|(t, _)| *t <= time) - 1;
+        let ((t1, p1), (t2, p2)) = (&keys[i], &keys[i + 1]);
+        let t = (time - t1) / (t2 - t1);
+        Some(match self.interpolation {
+            Interpolation::Linear => p1.lerp(p2, t),
+            Interpolation::Spline => {
+                // the end keys stand in for the missing neighbours
+                let p0 = &keys[i.saturating_sub(1)].1;
+                let p3 = &keys[(i + 2).min(keys.len() - 1)].1;
+                T::catmull_rom(p0, p1, p2, p3, t)
+            }
+        })
+    }
+}
+
+/// Keyframes for the arguments of `Camera::set_view`.
+#[derive(Debug, Clone)]
+pub struct ViewTrack {
+    pub from: Track<Vec4>,
+    pub to: Track<Vec4>,
+    pub up: Track<Vec4>,
+}
+
+/// Why an animation can't pose a world. Animated shapes and lights are
+/// changed in place, so they must exist and not be shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationError {
+    MissingShape(usize),
+    MissingLight(usize),
+    SharedShape(usize),
+    SharedLight(usize),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationError::MissingShape(index) => write!(f, "no shape at index {index}"),
+            AnimationError::MissingLight(index) => write!(f, "no light at index {index}"),
+            AnimationError::SharedShape(index) => {
+                write!(f, "animated shape {index} is shared and can't be moved")
+            }
+            AnimationError::SharedLight(index) => {
+                write!(f, "animated light {index} is shared and can't be moved")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+/// Animates a scene over time. Shapes and lights are referred to by their
+/// index in `World::shapes()` and `World::lights`, and must not be shared
+/// with anything else while the animation is applied.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub fps: f64,
+    pub view: Option<ViewTrack>,
+    pub shapes: Vec<(usize, Track<SqMatrix<4>>)>,
+    pub lights: Vec<(usize, Track<Vec4>)>,
+}
+
+impl Animation {
+    pub fn new(fps: f64) -> Self {
+        Animation {
+            fps,
+            view: None,
+            shapes: Vec::new(),
+            lights: Vec::new(),
+        }
+    }
+    pub fn animate_view(&mut self, from: Track<Vec4>, to: Track<Vec4>, up: Track<Vec4>) {
+        self.view = Some(ViewTrack { from, to, up });
+    }
+    /// Keyframes the transformation of `world.shapes()[index]`.
+    pub fn animate_shape(&mut self, index: usize, track: Track<SqMatrix<4>>) {
+        self.shapes.push((index, track));
+    }
+    /// Keyframes the position of `world.lights[index]`.
+    pub fn animate_light(&mut self, index: usize, track: Track<Vec4>) {
+        self.lights.push((index, track));
+    }
+
+    /// Poses the camera and the world at `time` in seconds. Nothing is
+    /// changed if an animated shape or light is missing or shared.
+    pub fn apply(
+        &self,
+        time: f64,
+        camera: &mut Camera,
+        world: &mut World,
+    ) -> Result<(), AnimationError> {
+        for (index, _) in &self.shapes {
+            match world.shapes().get(*index) {
+                None => return Err(AnimationError::MissingShape(*index)),
+                Some(shape) if !is_unique(shape) => {
+                    return Err(AnimationError::SharedShape(*index));
+                }
+                Some(_) => {}
+            }
+        }
+        for (index, _) in &self.lights {
+            match world.lights.get(*index) {
+                None => return Err(AnimationError::MissingLight(*index)),
+                Some(light) if !is_unique(light) => {
+                    return Err(AnimationError::SharedLight(*index));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(view) = &self.view
+            && let (Some(from), Some(to), Some(up)) = (
+                view.from.value_at(time),
+                view.to.value_at(time),
+                view.up.value_at(time),
+            )
+        {
+            camera.set_view(from, to, up);
+        }
+        for (index, track) in &self.shapes {
+            if let Some(transform) = track.value_at(time)
+                && let Some(shape) = Arc::get_mut(world.shape_mut(*index))
+            {
+                shape.set_transformation(transform);
+            }
+        }
+        for (index, track) in &self.lights {
+            if let Some(position) = track.value_at(time)
+                && let Some(light) = Arc::get_mut(&mut world.lights[*index])
+            {
+                light.set_position(position);
+            }
+        }
+        Ok(())
+    }
+
+    /// File name of a frame, `prefix` followed by the zero padded frame number.
+    pub fn frame_path(prefix: &str, frame: usize) -> String {
+        format!("{prefix}{frame:04}.png")
+    }
+
+    /// Renders every frame in `frames` to its `frame_path`. With
+    /// `skip_existing` frames that already have a file are left alone, so an
+    /// interrupted sequence can be resumed. Returns the paths written, or
+    /// the error of the first frame that can't be posed.
+    pub fn render(
+        &self,
+        camera: &mut Camera,
+        world: &mut World,
+        frames: Range<usize>,
+        prefix: &str,
+        skip_existing: bool,
+    ) -> Result<Vec<String>, AnimationError> {
+        let mut written = Vec::new();
+        for frame in frames {
+            let path = Animation::frame_path(prefix, frame);
+            if skip_existing && Path::new(&path).exists() {
+                continue;
+            }
+            self.apply(frame as f64 / self.fps, camera, world)?;
+            camera.render(world).save(&path);
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+// `Arc::get_mut` succeeds exactly when this holds
+fn is_unique<T: ?Sized>(arc: &Arc<T>) -> bool {
+    Arc::strong_count(arc) == 1 && Arc::weak_count(arc) == 0
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::Sphere;
+    use crate::color::Color;
+    use crate::light::PointLight;
+    use crate::matrix::Matrix;
+    use crate::ray::Ray;
+    use crate::shapes::Shape;
+
+    #[test]
+    fn linear_and_spline_tracks() {
+        let linear = Track::linear().key(2.0, 4.0).key(0.0, 0.0).key(1.0, 1.0);
+        let spline = Track::spline().key(0.0, 0.0).key(1.0, 1.0).key(2.0, 4.0);
+        let cases = [
+            (-1.0, 0.0, 0.0),
+            (0.5, 0.5, 0.3125),
+            (1.0, 1.0, 1.0),
+            (1.5, 2.5, 2.5625),
+            (3.0, 4.0, 4.0),
+        ];
+        for (time, l, s) in cases {
+            assert_eq!(linear.value_at(time), Some(l));
+            assert!((spline.value_at(time).unwrap() - s).abs() < 1e-12);
+        }
+        assert_eq!(Track::<f64>::linear().value_at(0.0), None);
+    }
+
+    #[test]
+    fn transform_tracks_interpolate_rotation() {
+        let track = Track::linear()
+            .key(0.0, Matrix::eye())
+            .key(1.0, Matrix::rotation_y(PI / 2.0));
+        assert_eq!(track.value_at(0.5).unwrap(), Matrix::rotation_y(PI / 4.0));
+    }
+
+    #[test]
+    fn apply_poses_camera_shapes_and_lights() {
+        let mut world = World::new(PointLight::new(Vec4::point(0.0, 0.0, 0.0), Color::white()));
+        world.add_shape(Arc::new(Sphere::new()));
+        let mut camera = Camera::new(11, 11, PI / 2.0, 0, 1);
+
+        let mut animation = Animation::new(24.0);
+        animation.animate_view(
+            Track::linear().key(0.0, Vec4::point(0.0, 0.0, -5.0)),
+            Track::linear()
+                .key(0.0, Vec4::point(0.0, 0.0, 0.0))
+                .key(1.0, Vec4::point(10.0, 0.0, -5.0)),
+            Track::linear().key(0.0, Vec4::vector(0.0, 1.0, 0.0)),
+        );
+        animation.animate_shape(
+            0,
+            Track::linear()
+                .key(0.0, Matrix::eye())
+                .key(2.0, Matrix::translation(0.0, 4.0, 0.0)),
+        );
+        animation.animate_light(0, Track::linear().key(0.0, Vec4::point(0.0, 10.0, 0.0)));
+
+        animation.apply(1.0, &mut camera, &mut world).unwrap();
+        assert_eq!(
+            camera.transform,
+            Camera::view_transform(
+                Vec4::point(0.0, 0.0, -5.0),
+                Vec4::point(10.0, 0.0, -5.0),
+                Vec4::vector(0.0, 1.0, 0.0),
+            )
+        );
+        assert_eq!(
+            world.shapes()[0].transform(),
+            &Matrix::translation(0.0, 2.0, 0.0)
+        );
+        assert_eq!(
+            world
+                .intersect(&Ray::new(0.0, 2.0, -5.0, 0.0, 0.0, 1.0))
+                .len(),
+            2
+        );
+        let sample = &world.lights[0].samples_at(&Vec4::point(0.0, 0.0, 0.0), &mut rand::rng())[0];
+        assert_eq!(sample.distance, 10.0);
+    }
+
+    #[test]
+    fn shared_shapes_and_lights_are_reported() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new());
+        let mut world = World::new(PointLight::new(Vec4::point(0.0, 0.0, 0.0), Color::white()));
+        world.add_shape(sphere.clone());
+        let mut camera = Camera::new(11, 11, PI / 2.0, 0, 1);
+        let track = Track::linear().key(0.0, Matrix::translation(0.0, 4.0, 0.0));
+
+        let mut animation = Animation::new(24.0);
+        animation.animate_shape(0, track.clone());
+        assert_eq!(
+            animation.apply(0.0, &mut camera, &mut world),
+            Err(AnimationError::SharedShape(0))
+        );
+        assert_eq!(world.shapes()[0].transform(), &Matrix::eye());
+
+        drop(sphere);
+        let light = world.lights[0].clone();
+        animation.animate_light(0, Track::linear().key(0.0, Vec4::point(0.0, 10.0, 0.0)));
+        assert_eq!(
+            animation.apply(0.0, &mut camera, &mut world),
+            Err(AnimationError::SharedLight(0))
+        );
+        assert_eq!(world.shapes()[0].transform(), &Matrix::eye());
+        drop(light);
+        assert_eq!(animation.apply(0.0, &mut camera, &mut world), Ok(()));
+
+        animation.animate_shape(3, track);
+        assert_eq!(
+            animation.apply(0.0, &mut camera, &mut world),
+            Err(AnimationError::MissingShape(3))
+        );
+    }
+
+    #[test]
+    fn render_skips_existing_frames() {
+        let dir = std::env::temp_dir().join(format!("raytracer-frames-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let prefix = format!("{}/frame", dir.display());
+        let mut world = World::default();
+        let mut camera = Camera::new(4, 4, PI / 2.0, 0, 1);
+        let animation = Animation::new(24.0);
+
+        let written = animation
+            .render(&mut camera, &mut world, 0..2, &prefix, true)
+            .unwrap();
+        assert_eq!(
+            written,
+            [format!("{prefix}0000.png"), format!("{prefix}0001.png")]
+        );
+        std::fs::remove_file(&written[1]).unwrap();
+        let written = animation
+            .render(&mut camera, &mut world, 0..3, &prefix, true)
+            .unwrap();
+        assert_eq!(
+            written,
+            [format!("{prefix}0001.png"), format!("{prefix}0002.png")]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
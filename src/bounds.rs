@@ -12,13 +12,77 @@ impl Bounds {
     pub fn new(min: Vec4, max: Vec4) -> Self {
         Self { min, max }
     }
+    pub fn empty() -> Self {
+        Self {
+            min: Vec4::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec4::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+    pub fn infinite() -> Self {
+        Self {
+            min: Vec4::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Vec4::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
     pub fn transform(&self, transformation: &Matrix<4, 4>) -> Bounds {
-        let new_min = transformation * &self.min;
-        let new_max = transformation * &self.max;
-        Bounds {
-            min: new_min,
-            max: new_max,
+        if !self.is_finite() {
+            return Bounds::infinite();
+        }
+        // all eight corners have to be transformed, otherwise rotations flip min and max
+        let mut bounds = Bounds::empty();
+        for x in [self.min.x, self.max.x] {
+            for y in [self.min.y, self.max.y] {
+                for z in [self.min.z, self.max.z] {
+                    bounds.add_point(&(transformation * &Vec4::point(x, y, z)));
+                }
+            }
+        }
+        bounds
+    }
+    pub fn add_point(&mut self, point: &Vec4) {
+        self.min.x = self.min.x.min(point.x);
+        self.min.y = self.min.y.min(point.y);
+        self.min.z = self.min.z.min(point.z);
+        self.max.x = self.max.x.max(point.x);
+        self.max.y = self.max.y.max(point.y);
+        self.max.z = self.max.z.max(point.z);
+    }
+    pub fn merge(&self, other: &Bounds) -> Bounds {
+        Bounds::new(
+            Vec4::point(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Vec4::point(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+    pub fn centroid(&self) -> Vec4 {
+        Vec4::point(
+            (self.min.x + self.max.x) * 0.5,
+            (self.min.y + self.max.y) * 0.5,
+            (self.min.z + self.max.z) * 0.5,
+        )
+    }
+    pub fn axis(point: &Vec4, axis: usize) -> f64 {
+        match axis {
+            0 => point.x,
+            1 => point.y,
+            _ => point.z,
+        }
+    }
+    pub fn surface_area(&self) -> f64 {
+        let dx = self.max.x - self.min.x;
+        let dy = self.max.y - self.min.y;
+        let dz = self.max.z - self.min.z;
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
         }
+        2.0 * (dx * dy + dy * dz + dz * dx)
     }
     pub fn is_finite(&self) -> bool {
         self.min.x.is_finite()
@@ -84,11 +148,30 @@ pub mod tests {
         g2.add_child(Arc::new(g));
         g2.set_transformation(Matrix::scaling(5.0, 1.0, 1.0));
         assert_eq!(
-            g2.bounds(),
+            Group::child_bounds(&g2),
             Bounds::new(Vec4::point(-25.0, -1.0, -1.0), Vec4::point(25.0, 1.0, 1.0))
         );
     }
 
+    #[test]
+    fn transform_rotated_bounds() {
+        let b = Bounds::new(Vec4::point(-1.0, -1.0, -1.0), Vec4::point(1.0, 1.0, 1.0));
+        let t = b.transform(&Matrix::rotation_y(std::f64::consts::PI / 4.0));
+        let r = std::f64::consts::SQRT_2;
+        assert_eq!(t, Bounds::new(Vec4::point(-r, -1.0, -r), Vec4::point(r, 1.0, r)));
+    }
+
+    #[test]
+    fn merge_and_surface_area() {
+        let a = Bounds::new(Vec4::point(0.0, 0.0, 0.0), Vec4::point(1.0, 1.0, 1.0));
+        let b = Bounds::new(Vec4::point(2.0, 0.0, 0.0), Vec4::point(3.0, 1.0, 1.0));
+        let m = a.merge(&b);
+        assert_eq!(m, Bounds::new(Vec4::point(0.0, 0.0, 0.0), Vec4::point(3.0, 1.0, 1.0)));
+        assert_eq!(m.surface_area(), 14.0);
+        assert_eq!(Bounds::empty().merge(&a), a);
+        assert_eq!(Bounds::empty().surface_area(), 0.0);
+    }
+
     #[test]
     fn test_teapot() {
         let mut p = Parser::new();
@@ -0,0 +1,308 @@
+use crate::{bounds::Bounds, ray::Ray, vec4::Vec4};
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Bounds,
+    // leaf: index of the first primitive in `indices`, interior: index of the left child
+    first: usize,
+    count: usize,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    bounds: Bounds,
+    count: usize,
+}
+
+/// Bounding volume hierarchy over a list of primitive bounds, built with the
+/// binned surface area heuristic. Nodes are stored in a flat array, the two
+/// children of an interior node are always adjacent.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+    size: usize,
+}
+
+impl Bvh {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build(bounds: &[Bounds]) -> Self {
+        let mut indices = Vec::with_capacity(bounds.len());
+        let mut unbounded = Vec::new();
+        for (i, b) in bounds.iter().enumerate() {
+            if b.is_finite() {
+                indices.push(i);
+            } else {
+                unbounded.push(i);
+            }
+        }
+        let centroids = bounds.iter().map(|b| b.centroid()).collect::<Vec<_>>();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * indices.len()),
+            indices,
+            unbounded,
+            size: bounds.len(),
+        };
+        if !bvh.indices.is_empty() {
+            bvh.nodes.push(BvhNode {
+                bounds: Bounds::empty(),
+                first: 0,
+                count: bvh.indices.len(),
+            });
+            bvh.subdivide(0, bounds, &centroids);
+        }
+        bvh
+    }
+
+    /// Number of primitives the hierarchy was built for.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Bounds of all finite primitives.
+    pub fn bounds(&self) -> Bounds {
+        self.nodes.first().map_or(Bounds::empty(), |n| n.bounds)
+    }
+
+    /// Calls `visit` with the index of every primitive whose bounds are hit by the ray.
+    /// Primitives with infinite bounds are always visited.
+    pub fn traverse(&self, ray: &Ray, mut visit: impl FnMut(usize)) {
+        for &i in &self.unbounded {
+            visit(i);
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if !node.bounds.intersection(ray) {
+                continue;
+            }
+            if node.is_leaf() {
+                for &i in &self.indices[node.first..node.first + node.count] {
+                    visit(i);
+                }
+            } else {
+                stack.push(node.first + 1);
+                stack.push(node.first);
+            }
+        }
+    }
+
+    fn subdivide(&mut self, node_index: usize, bounds: &[Bounds], centroids: &[Vec4]) {
+        let first = self.nodes[node_index].first;
+        let count = self.nodes[node_index].count;
+        let range = first..first + count;
+
+        let mut node_bounds = Bounds::empty();
+        let mut centroid_bounds = Bounds::empty();
+        for &i in &self.indices[range.clone()] {
+            node_bounds = node_bounds.merge(&bounds[i]);
+            centroid_bounds.add_point(&centroids[i]);
+        }
+        self.nodes[node_index].bounds = node_bounds;
+
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        let Some((axis, split, cost)) = self.find_split(
+            range.clone(),
+            &node_bounds,
+            &centroid_bounds,
+            bounds,
+            centroids,
+        ) else {
+            return;
+        };
+        if cost >= count as f64 * INTERSECTION_COST {
+            return;
+        }
+
+        let min = Bounds::axis(&centroid_bounds.min, axis);
+        let extent = Bounds::axis(&centroid_bounds.max, axis) - min;
+        let bin_of = |i: usize| {
+            let c = Bounds::axis(&centroids[i], axis);
+            (((c - min) / extent * BINS as f64) as usize).min(BINS - 1)
+        };
+
+        // partition the primitives in place around the chosen bin boundary
+        let mut i = first;
+        let mut j = first + count;
+        while i < j {
+            if bin_of(self.indices[i]) < split {
+                i += 1;
+            } else {
+                j -= 1;
+                self.indices.swap(i, j);
+            }
+        }
+        let left_count = i - first;
+        if left_count == 0 || left_count == count {
+            return;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: Bounds::empty(),
+            first,
+            count: left_count,
+        });
+        self.nodes.push(BvhNode {
+            bounds: Bounds::empty(),
+            first: i,
+            count: count - left_count,
+        });
+        self.nodes[node_index].first = left;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(left, bounds, centroids);
+        self.subdivide(left + 1, bounds, centroids);
+    }
+
+    fn find_split(
+        &self,
+        range: std::ops::Range<usize>,
+        node_bounds: &Bounds,
+        centroid_bounds: &Bounds,
+        bounds: &[Bounds],
+        centroids: &[Vec4],
+    ) -> Option<(usize, usize, f64)> {
+        let parent_area = node_bounds.surface_area();
+        let mut best: Option<(usize, usize, f64)> = None;
+
+        for axis in 0..3 {
+            let min = Bounds::axis(&centroid_bounds.min, axis);
+            let extent = Bounds::axis(&centroid_bounds.max, axis) - min;
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bins = [Bin {
+                bounds: Bounds::empty(),
+                count: 0,
+            }; BINS];
+            for &i in &self.indices[range.clone()] {
+                let c = Bounds::axis(&centroids[i], axis);
+                let b = (((c - min) / extent * BINS as f64) as usize).min(BINS - 1);
+                bins[b].count += 1;
+                bins[b].bounds = bins[b].bounds.merge(&bounds[i]);
+            }
+
+            // sweep from the right to get the area and count of every right hand side
+            let mut right_area = [0.0; BINS];
+            let mut right_count = [0; BINS];
+            let mut acc = Bounds::empty();
+            let mut n = 0;
+            for b in (1..BINS).rev() {
+                acc = acc.merge(&bins[b].bounds);
+                n += bins[b].count;
+                right_area[b] = acc.surface_area();
+                right_count[b] = n;
+            }
+
+            let mut acc = Bounds::empty();
+            let mut n = 0;
+            for split in 1..BINS {
+                acc = acc.merge(&bins[split - 1].bounds);
+                n += bins[split - 1].count;
+                if n == 0 || right_count[split] == 0 {
+                    continue;
+                }
+                let cost = if parent_area > 0.0 {
+                    TRAVERSAL_COST
+                        + INTERSECTION_COST
+                            * (acc.surface_area() * n as f64
+                                + right_area[split] * right_count[split] as f64)
+                            / parent_area
+                } else {
+                    TRAVERSAL_COST + INTERSECTION_COST * n.max(right_count[split]) as f64
+                };
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, split, cost));
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{bounds::Bounds, ray::Ray, vec4::Vec4};
+
+    use super::Bvh;
+
+    fn unit_box_at(x: f64) -> Bounds {
+        Bounds::new(
+            Vec4::point(x - 0.5, -0.5, -0.5),
+            Vec4::point(x + 0.5, 0.5, 0.5),
+        )
+    }
+
+    #[test]
+    fn traverse_visits_only_hit_primitives() {
+        let bounds = (0..100)
+            .map(|i| unit_box_at(i as f64 * 2.0))
+            .collect::<Vec<_>>();
+        let bvh = Bvh::build(&bounds);
+        assert_eq!(bvh.len(), 100);
+        assert_eq!(
+            bvh.bounds(),
+            Bounds::new(Vec4::point(-0.5, -0.5, -0.5), Vec4::point(198.5, 0.5, 0.5))
+        );
+
+        let r = Ray::new(40.0, 5.0, 0.0, 0.0, -1.0, 0.0);
+        let mut visited = Vec::new();
+        bvh.traverse(&r, |i| visited.push(i));
+        // whole leaves are visited, the primitives do the exact test
+        assert!(visited.contains(&20));
+        assert!(visited.len() <= super::MAX_LEAF_SIZE);
+    }
+
+    #[test]
+    fn traverse_visits_every_primitive_along_the_ray() {
+        let bounds = (0..100)
+            .map(|i| unit_box_at(i as f64 * 2.0))
+            .collect::<Vec<_>>();
+        let bvh = Bvh::build(&bounds);
+
+        let r = Ray::new(-5.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let mut visited = Vec::new();
+        bvh.traverse(&r, |i| visited.push(i));
+        visited.sort();
+        assert_eq!(visited, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unbounded_primitives_are_always_visited() {
+        let bounds = vec![unit_box_at(0.0), Bounds::infinite(), unit_box_at(10.0)];
+        let bvh = Bvh::build(&bounds);
+
+        let r = Ray::new(0.0, 5.0, 5.0, 0.0, 1.0, 0.0);
+        let mut visited = Vec::new();
+        bvh.traverse(&r, |i| visited.push(i));
+        assert_eq!(visited, vec![1]);
+    }
+}
@@ -1,11 +1,56 @@
 use crate::matrix::SqMatrix;
 use crate::{canvas::Canvas, color::Color, matrix::Matrix, ray::Ray, vec4::Vec4, world::World};
-use indicatif::{ProgressBar, ProgressStyle};
 use rayon::ThreadPoolBuilder;
 use rayon::prelude::*;
-use rand::Rng;
-use array_init::array_init;
-use crate::SAMPLES_PER_PIXEL;
+use crate::sampling::{Sampler, Stratified};
+use crate::canvas::{MAX_WAVELENGTH, MIN_WAVELENGTH, PixelStats};
+use crate::checkpoint::Checkpoint;
+use crate::intersection::Intersection;
+use crate::progress::{CancellationToken, Progress, ProgressBarObserver, ProgressObserver};
+use crate::projection::{Perspective, Projection};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Adaptive anti-aliasing: every pixel starts with `min_samples` rays and
+/// keeps getting `min_samples` more, up to `max_samples`, while the standard
+/// error of its luminance or the contrast to a neighbour is above `threshold`.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub threshold: f64,
+}
+
+/// Part of the image handed out by `Camera::render_tiles`. `pixels` are in
+/// row order and hold the average of all samples taken up to `pass`.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub pass: usize,
+    pub passes: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Tile {
+    pub fn is_final(&self) -> bool {
+        self.pass + 1 == self.passes
+    }
+    /// Copies the tile into its place on `canvas`.
+    pub fn write_to(&self, canvas: &mut Canvas) {
+        for (i, color) in self.pixels.iter().enumerate() {
+            canvas.set_pixel(self.x + i % self.width, self.y + i / self.width, *color);
+        }
+    }
+}
 
 pub struct Camera {
     pub hsize: usize,
@@ -18,6 +63,21 @@ pub struct Camera {
     pub reflection_max: usize,
     pub max_threads: usize,
     pub inverse: SqMatrix<4>,
+    pub samples_per_pixel: usize,
+    pub sampler: Box<dyn Sampler>,
+    pub adaptive: Option<AdaptiveSampling>,
+    pub seed: u64,
+    pub aperture: f64,
+    pub focal_distance: f64,
+    pub blades: usize,
+    pub projection: Box<dyn Projection>,
+    pub shutter: (f64, f64),
+    pub tile_size: usize,
+    pub passes: usize,
+    pub progress: Box<dyn ProgressObserver>,
+    pub cancellation: CancellationToken,
+    pub checkpoint: Option<Checkpoint>,
+    pub spectral: bool,
 }
 
 impl Camera {
@@ -49,14 +109,114 @@ impl Camera {
             reflection_max,
             max_threads,
             inverse: Matrix::eye(),
+            samples_per_pixel: 32,
+            sampler: Box::new(Stratified),
+            adaptive: None,
+            seed: 0,
+            aperture: 0.0,
+            focal_distance: 1.0,
+            blades: 0,
+            projection: Box::new(Perspective),
+            shutter: (0.0, 0.0),
+            tile_size: 16,
+            passes: 1,
+            progress: Box::new(ProgressBarObserver::new()),
+            cancellation: CancellationToken::new(),
+            checkpoint: None,
+            spectral: false,
         }
     }
+    /// Anti-aliasing settings. With a single sample every ray goes through
+    /// the pixel center, otherwise `sampler` places the samples. A new camera
+    /// takes 32 stratified samples.
+    pub fn set_sampling(&mut self, samples_per_pixel: usize, sampler: impl Sampler + 'static) {
+        self.samples_per_pixel = samples_per_pixel.max(1);
+        self.sampler = Box::new(sampler);
+    }
+    /// Seed for all sampling randomness. Renders with the same seed are
+    /// identical, no matter how many threads work on them.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+    /// Replaces the default perspective projection, `fov` only applies to
+    /// the perspective one.
+    pub fn set_projection(&mut self, projection: impl Projection + 'static) {
+        self.projection = Box::new(projection);
+    }
+    /// Time interval the shutter is open, the samples of a pixel are spread
+    /// over it so that moving shapes blur.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter = (open, close);
+    }
+    /// Turns the pinhole into a thin lens with radius `aperture` that is in
+    /// focus at `focal_distance` along the view direction. The blur only
+    /// shows with more than one sample per pixel.
+    pub fn set_depth_of_field(&mut self, aperture: f64, focal_distance: f64) {
+        self.aperture = aperture;
+        self.focal_distance = focal_distance;
+    }
+    /// Shapes the aperture as a regular polygon with `blades` sides, zero
+    /// keeps it a disk.
+    pub fn set_aperture_blades(&mut self, blades: usize) {
+        self.blades = if blades < 3 { 0 } else { blades };
+    }
+    /// Sets the focal distance to the first hit seen through the center of
+    /// the pixel, returns the new distance or `None` if the ray hits nothing.
+    pub fn focus_on(&mut self, world: &World, px: usize, py: usize) -> Option<f64> {
+        let local = self
+            .projection
+            .ray(self, px as f64 + 0.5, py as f64 + 0.5, (0.0, 0.0))?;
+        let xs = world.intersect(&local.transform(&self.inverse));
+        let hit = Intersection::hit(&xs)?;
+        // project the hit distance onto the view direction
+        self.focal_distance = hit.t * -local.direction.z;
+        Some(self.focal_distance)
+    }
+    /// Splits the render into square tiles of `tile_size` pixels and, unless
+    /// adaptive sampling is on, spreads the samples over `passes` passes that
+    /// each refine the whole image.
+    pub fn set_tiles(&mut self, tile_size: usize, passes: usize) {
+        self.tile_size = tile_size.max(1);
+        self.passes = passes.max(1);
+    }
+    /// Replaces the terminal progress bar, e.g. with `progress::Silent`.
+    pub fn set_progress(&mut self, observer: impl ProgressObserver + 'static) {
+        self.progress = Box::new(observer);
+    }
+    /// Cancelling `token` stops the next render early, it then returns the
+    /// image as far as it got. Keep a clone to cancel from another thread.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+    /// Gives every sample a wavelength so that materials with `dispersion`
+    /// split white light into colours. Each sample only carries one
+    /// wavelength, so this needs many samples per pixel to converge.
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral = spectral;
+    }
+    /// Saves the samples taken so far to `path` every `interval` and at the
+    /// end of the render. A render that finds the file picks up where it
+    /// left off and only adds the samples that are still missing, so raising
+    /// the sample count refines a finished image.
+    pub fn set_checkpoint(&mut self, path: impl Into<PathBuf>, interval: Duration) {
+        self.checkpoint = Some(Checkpoint::new(path, interval));
+    }
+    /// Switches to adaptive anti-aliasing, `samples_per_pixel` is ignored
+    /// while it is set.
+    pub fn set_adaptive_sampling(&mut self, min_samples: usize, max_samples: usize, threshold: f64) {
+        let min_samples = min_samples.max(1);
+        self.adaptive = Some(AdaptiveSampling {
+            min_samples,
+            max_samples: max_samples.max(min_samples),
+            threshold,
+        });
+    }
     pub fn set_view(&mut self, from: Vec4, to: Vec4, up: Vec4) {
         self.transform = Camera::view_transform(from, to, up);
         self.inverse = self.transform.inverse();
     }
     pub fn set_view_from_matrix(&mut self, mat: Matrix<4, 4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
     pub fn view_transform(from: Vec4, to: Vec4, up: Vec4) -> Matrix<4, 4> {
@@ -72,104 +232,347 @@ impl Camera {
         ];
         Matrix::from_array(val) * Matrix::translation(-from.x, -from.y, -from.z)
     }
-    pub fn rays_for_pixels(&self, px: usize, py: usize) -> [Ray; SAMPLES_PER_PIXEL] {
-        let mut rng = rand::rng();
-        let n_sqrt = (SAMPLES_PER_PIXEL as f64).sqrt().round() as usize;
-        debug_assert_eq!(n_sqrt * n_sqrt, SAMPLES_PER_PIXEL, "SAMPLES_PER_PIXEL must be a perfect square");
+    /// Sample rays for a pixel, samples the projection doesn't cover are
+    /// left out.
+    pub fn rays_for_pixels(&self, px: usize, py: usize) -> Vec<Ray> {
+        self.sample_rays(px, py, self.samples_per_pixel, &mut self.pixel_rng(px, py, 0))
+            .into_iter()
+            .flatten()
+            .collect()
+    }
 
-        array_init(|i| {
-            let xi = i % n_sqrt;
-            let yi = i / n_sqrt;
+    /// Random stream for one batch of samples of a pixel. It only depends on
+    /// the seed, the pixel and the number of samples the pixel already has.
+    pub fn pixel_rng(&self, px: usize, py: usize, batch: u64) -> StdRng {
+        // splitmix64 finalizer to decorrelate neighbouring pixels
+        let mix = |mut z: u64| {
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        let pixel = (py as u64) << 32 | px as u64;
+        StdRng::seed_from_u64(mix(mix(mix(self.seed) ^ pixel) ^ batch))
+    }
 
-            let dx: f64 = (xi as f64 + rng.random::<f64>()) / n_sqrt as f64;
-            let dy: f64 = (yi as f64 + rng.random::<f64>()) / n_sqrt as f64;
+    fn sample_rays(&self, px: usize, py: usize, n: usize, rng: &mut StdRng) -> Vec<Option<Ray>> {
+        let samples = self.sampler.samples(n, rng);
+        let times = shuffled_strata(n, rng)
+            .into_iter()
+            .map(|u| self.shutter.0 + u * (self.shutter.1 - self.shutter.0));
+        let wavelengths = if self.spectral {
+            shuffled_strata(n, rng)
+                .into_iter()
+                .map(|u| Some(MIN_WAVELENGTH + u * (MAX_WAVELENGTH - MIN_WAVELENGTH)))
+                .collect()
+        } else {
+            vec![None; n]
+        };
+        samples
+            .into_iter()
+            .zip(times)
+            .zip(wavelengths)
+            .map(|(((dx, dy), time), wavelength)| {
+                let lens = if self.aperture > 0.0 {
+                    self.sample_lens(rng)
+                } else {
+                    (0.0, 0.0)
+                };
+                self.ray_through(px as f64 + dx, py as f64 + dy, lens)
+                    .map(|ray| ray.at_time(time).with_wavelength(wavelength))
+            })
+            .collect()
+    }
 
-            let xoffset = (px as f64 + dx) * self.pixel_size;
-            let yoffset = (py as f64 + dy) * self.pixel_size;
+    // uniform point on the aperture, a disk or a polygon made of `blades` triangles
+    fn sample_lens(&self, rng: &mut StdRng) -> (f64, f64) {
+        let (u, v): (f64, f64) = (rng.random(), rng.random());
+        let (x, y) = if self.blades == 0 {
+            let (r, theta) = (u.sqrt(), 2.0 * PI * v);
+            (r * theta.cos(), r * theta.sin())
+        } else {
+            let n = self.blades as f64;
+            let blade = (u * n).floor();
+            let u = u * n - blade;
+            let (a0, a1) = (2.0 * PI * blade / n, 2.0 * PI * (blade + 1.0) / n);
+            let (s, t) = (u.sqrt() * (1.0 - v), u.sqrt() * v);
+            (s * a0.cos() + t * a1.cos(), s * a0.sin() + t * a1.sin())
+        };
+        (x * self.aperture, y * self.aperture)
+    }
 
-            let world_x = self.half_width - xoffset;
-            let world_y = self.half_height - yoffset;
+    /// Ray through the center of the pixel at the moment the shutter opens.
+    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Option<Ray> {
+        self.ray_through(px as f64 + 0.5, py as f64 + 0.5, (0.0, 0.0))
+            .map(|ray| ray.at_time(self.shutter.0))
+    }
 
-            let pixel = &self.inverse * &Vec4::point(world_x, world_y, -1.0);
-            let origin = &self.inverse * &Vec4::point(0.0, 0.0, 0.0);
-            let direction = (pixel - origin).norm();
+    // ray from `lens` on the aperture through a position on the canvas,
+    // measured in pixels from the top left
+    fn ray_through(&self, x: f64, y: f64, lens: (f64, f64)) -> Option<Ray> {
+        let local = self.projection.ray(self, x, y, lens)?;
+        let origin = self.inverse * local.origin;
+        let direction = (self.inverse * local.direction).norm();
+        Some(Ray::from_vec4(origin, direction))
+    }
 
-            Ray { origin, direction }
-        })
+    // color seen along a sample, black where the projection has no ray
+    fn trace(&self, world: &World, ray: Option<Ray>, rng: &mut StdRng) -> Color {
+        ray.map_or(Color::black(), |ray| world.color_at(&ray, self.reflection_max, rng))
     }
 
-    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        let px = px as f64;
-        let py = py as f64;
-        let xoffset = (px + 0.5) * self.pixel_size;
-        let yoffset = (py + 0.5) * self.pixel_size;
+    pub fn render(&self, world: &World) -> Canvas {
+        self.render_with_heatmap(world).0
+    }
 
-        let world_x = self.half_width - xoffset;
-        let world_y = self.half_height - yoffset;
-        let pixel = &self.inverse * &Vec4::point(world_x, world_y, -1.0);
-        let origin = &self.inverse * &Vec4::point(0.0, 0.0, 0.0);
-        let direction = (pixel - origin).norm();
-        Ray { origin, direction }
+    /// Renders the image together with a heat map of the number of samples
+    /// spent on each pixel, white being the most samples any pixel can get.
+    pub fn render_with_heatmap(&self, world: &World) -> (Canvas, Canvas) {
+        self.render_tiles(world, |_| {})
     }
 
-    pub fn render(&self, world: &World) -> Canvas {
+    /// Renders tile by tile and hands every finished tile to `on_tile`, once
+    /// per pass. Tiles arrive from the worker threads in no particular
+    /// order, each holding the image as it looks after that pass. Returns
+    /// the same as `render_with_heatmap`.
+    pub fn render_tiles<F>(&self, world: &World, on_tile: F) -> (Canvas, Canvas)
+    where
+        F: Fn(&Tile) + Sync,
+    {
         let mut image = Canvas::new(self.hsize, self.vsize);
+        let mut heatmap = Canvas::new(self.hsize, self.vsize);
 
         let total_pixels = (self.hsize * self.vsize) as u64;
+        // adaptive sampling does one pass to find the noisy pixels and one to refine them
+        let passes = match self.adaptive {
+            Some(_) => 2,
+            None => self.passes.min(self.samples_per_pixel).max(1),
+        };
 
-        let bar = ProgressBar::new(total_pixels);
-        bar.set_style(
-            ProgressStyle::with_template(
-                "[{elapsed_precise}] [{wide_bar}] {pos}/{len} ({percent}%)",
-            )
-            .unwrap()
-            .progress_chars("=>-"),
-        );
+        let start = Instant::now();
+        let total = total_pixels * passes as u64;
+        let pixels_done = AtomicU64::new(0);
+        let rays = AtomicU64::new(0);
+        let progress = || Progress {
+            pixels_done: pixels_done.load(Ordering::Relaxed),
+            total_pixels: total,
+            rays: rays.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+        };
+        self.progress.start(total);
 
         let pool = ThreadPoolBuilder::new()
             .num_threads(self.max_threads)
             .build()
             .unwrap();
 
-        let pixels: Vec<(usize, usize)> = (0..self.vsize)
-            .flat_map(|y| (0..self.hsize).map(move |x| (x, y)))
+        let tiles: Vec<(usize, usize)> = (0..self.vsize)
+            .step_by(self.tile_size)
+            .flat_map(|y| (0..self.hsize).step_by(self.tile_size).map(move |x| (x, y)))
             .collect();
-        let results: Vec<((usize, usize), Color)> = pool
-            .install(|| {
-                pixels.into_par_iter().map(|(x, y)| {
-                    let color: Color = if SAMPLES_PER_PIXEL == 1 {
-                        let ray = self.ray_for_pixel(x, y);
-                        world.color_at(&ray, self.reflection_max)
-                    } else {
-                        let rays = self.rays_for_pixels(x, y);
-                        let mut color_avg = Color::black();
-                        for ray in rays {
-                            color_avg += world.color_at(&ray, self.reflection_max);
+        let mut stats = self.resume();
+        let last_save = Mutex::new(Instant::now());
+        for pass in 0..passes {
+            // finished tiles go here right away so checkpoints see them
+            let current = Mutex::new(stats.clone());
+            pool.install(|| {
+                tiles
+                    .par_iter()
+                    .filter(|_| !self.cancellation.is_cancelled())
+                    .for_each(|&(tx, ty)| {
+                        let width = self.tile_size.min(self.hsize - tx);
+                        let height = self.tile_size.min(self.vsize - ty);
+                        let mut tile_stats = Vec::with_capacity(width * height);
+                        let mut tile_rays = 0;
+                        for y in ty..ty + height {
+                            for x in tx..tx + width {
+                                let mut pixel = stats[y * self.hsize + x];
+                                let before = pixel.count;
+                                self.render_pass(world, x, y, pass, passes, &stats, &mut pixel);
+                                tile_rays += (pixel.count - before) as u64;
+                                tile_stats.push(pixel);
+                            }
                         }
-                        color_avg / SAMPLES_PER_PIXEL as f64
-                    };
-                    bar.inc(1);
-                    ((x, y), color)
-                })
-            })
-            .collect();
+                        let tile = Tile {
+                            x: tx,
+                            y: ty,
+                            width,
+                            height,
+                            pass,
+                            passes,
+                            pixels: tile_stats.iter().map(PixelStats::mean).collect(),
+                        };
+                        on_tile(&tile);
+                        let mut current = current.lock().unwrap();
+                        for (i, pixel) in tile_stats.into_iter().enumerate() {
+                            current[(ty + i / width) * self.hsize + tx + i % width] = pixel;
+                        }
+                        // the image is copied under the lock and written without it,
+                        // a thread that finds another one saving skips this save
+                        let save = self.checkpoint.as_ref().and_then(|checkpoint| {
+                            let last_save = last_save.try_lock().ok()?;
+                            (last_save.elapsed() >= checkpoint.interval)
+                                .then(|| (checkpoint, last_save, current.clone()))
+                        });
+                        drop(current);
+                        if let Some((checkpoint, mut last_save, snapshot)) = save {
+                            self.save_checkpoint(checkpoint, &snapshot);
+                            *last_save = Instant::now();
+                        }
+                        pixels_done.fetch_add((width * height) as u64, Ordering::Relaxed);
+                        rays.fetch_add(tile_rays, Ordering::Relaxed);
+                        self.progress.update(&progress());
+                    })
+            });
+            stats = current.into_inner().unwrap();
+        }
+
+        if let Some(checkpoint) = &self.checkpoint {
+            self.save_checkpoint(checkpoint, &stats);
+        }
+        self.progress.finish(&progress());
+
+        let max_samples = self
+            .adaptive
+            .map_or(self.samples_per_pixel, |a| a.max_samples) as f64;
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let pixel = &stats[y * self.hsize + x];
+                image.set_pixel(x, y, pixel.mean());
+                let heat = pixel.count as f64 / max_samples;
+                heatmap.set_pixel(x, y, Color::new(heat, heat, heat));
+            }
+        }
+        (image, heatmap)
+    }
+
+    // samples of an earlier run from the checkpoint, or an empty image if
+    // there is none or it can't be used
+    fn resume(&self) -> Vec<PixelStats> {
+        let empty = || vec![PixelStats::new(); self.hsize * self.vsize];
+        let Some(checkpoint) = &self.checkpoint else {
+            return empty();
+        };
+        match checkpoint.load(self.hsize, self.vsize) {
+            Ok(stats) => stats.unwrap_or_else(empty),
+            Err(e) => {
+                self.progress.warning(&format!(
+                    "can't resume from checkpoint {}: {e}, starting over",
+                    checkpoint.path.display()
+                ));
+                empty()
+            }
+        }
+    }
 
-        bar.finish();
+    // a failed save shouldn't throw away hours of rendering, the next one
+    // may well succeed
+    fn save_checkpoint(&self, checkpoint: &Checkpoint, stats: &[PixelStats]) {
+        if let Err(e) = checkpoint.save(self.hsize, self.vsize, stats) {
+            self.progress.warning(&format!(
+                "can't save checkpoint {}: {e}",
+                checkpoint.path.display()
+            ));
+        }
+    }
 
-        for ((x, y), color) in results {
-            image.set_pixel(x, y, color);
+    // adds the samples of one pass to `pixel`, `previous` holds the whole
+    // image after the last pass
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        world: &World,
+        x: usize,
+        y: usize,
+        pass: usize,
+        passes: usize,
+        previous: &[PixelStats],
+        pixel: &mut PixelStats,
+    ) {
+        match self.adaptive {
+            Some(adaptive) if pass > 0 => {
+                let contrast = self.neighbour_contrast(previous, x, y);
+                while pixel.count < adaptive.max_samples
+                    && (contrast > adaptive.threshold
+                        || pixel.standard_error() > adaptive.threshold)
+                {
+                    let n = adaptive.min_samples.min(adaptive.max_samples - pixel.count);
+                    self.sample_pixel(world, x, y, n, pixel);
+                }
+            }
+            // a resumed pixel may already have some or all of its samples
+            Some(adaptive) if adaptive.min_samples == 1 && !self.spectral => {
+                if pixel.count == 0 {
+                    let mut rng = self.pixel_rng(x, y, 0);
+                    pixel.add(self.trace(world, self.ray_for_pixel(x, y), &mut rng));
+                }
+            }
+            Some(adaptive) => {
+                let n = adaptive.min_samples.saturating_sub(pixel.count);
+                self.sample_pixel(world, x, y, n, pixel);
+            }
+            None if self.samples_per_pixel == 1 && !self.spectral => {
+                if pixel.count == 0 {
+                    let mut rng = self.pixel_rng(x, y, 0);
+                    pixel.add(self.trace(world, self.ray_for_pixel(x, y), &mut rng));
+                }
+            }
+            None => {
+                // spread the samples evenly over the passes
+                let target = self.samples_per_pixel * (pass + 1) / passes;
+                let n = target.saturating_sub(pixel.count);
+                self.sample_pixel(world, x, y, n, pixel);
+            }
         }
-        image
     }
+
+    // the rays and everything traced along them draw from the same stream
+    fn sample_pixel(&self, world: &World, x: usize, y: usize, n: usize, stats: &mut PixelStats) {
+        let mut rng = self.pixel_rng(x, y, stats.count as u64);
+        for ray in self.sample_rays(x, y, n, &mut rng) {
+            let wavelength = ray.and_then(|ray| ray.wavelength);
+            stats.add_sample(self.trace(world, ray, &mut rng), wavelength);
+        }
+    }
+
+    // largest luminance difference to the four direct neighbours
+    fn neighbour_contrast(&self, stats: &[PixelStats], x: usize, y: usize) -> f64 {
+        let l = stats[y * self.hsize + x].mean_luminance();
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .filter_map(|(dx, dy)| {
+                let nx = x.checked_add_signed(dx).filter(|&nx| nx < self.hsize)?;
+                let ny = y.checked_add_signed(dy).filter(|&ny| ny < self.vsize)?;
+                Some((stats[ny * self.hsize + nx].mean_luminance() - l).abs())
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+// one value per stratum of [0, 1), shuffled so they don't line up with the
+// sample positions
+fn shuffled_strata(n: usize, rng: &mut StdRng) -> Vec<f64> {
+    let mut values: Vec<f64> = (0..n)
+        .map(|i| (i as f64 + rng.random::<f64>()) / n as f64)
+        .collect();
+    values.shuffle(rng);
+    values
 }
 
 #[cfg(test)]
 pub mod tests {
     use std::f64::consts::{PI, SQRT_2};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
-    use crate::{color::Color, matrix::Matrix, vec4::Vec4, world::World};
+    use crate::{color::Color, math::EPSILON, matrix::Matrix, vec4::Vec4, world::World};
 
     use super::Camera;
+    use crate::canvas::Canvas;
+    use crate::light::AreaLight;
+    use crate::progress::{CancellationToken, Progress, ProgressObserver, Silent};
+    use crate::sampling::{Halton, Stratified};
+    use crate::shapes::Shape;
+    use crate::Sphere;
 
     #[test]
     fn default_orientation() {
@@ -199,14 +602,14 @@ pub mod tests {
     #[test]
     fn center_canvas() {
         let c = Camera::new(201, 101, PI / 2.0, 0, 1);
-        let r = c.ray_for_pixel(100, 50);
+        let r = c.ray_for_pixel(100, 50).unwrap();
         assert_eq!(r.origin, Vec4::point(0.0, 0.0, 0.0));
         assert_eq!(r.direction, Vec4::vector(0.0, 0.0, -1.0));
     }
     #[test]
     fn corner_canvas() {
         let c = Camera::new(201, 101, PI / 2.0, 0, 1);
-        let r = c.ray_for_pixel(0, 0);
+        let r = c.ray_for_pixel(0, 0).unwrap();
         assert_eq!(r.origin, Vec4::point(0.0, 0.0, 0.0));
         assert_eq!(r.direction, Vec4::vector(0.66519, 0.33259, -0.66851));
     }
@@ -214,12 +617,26 @@ pub mod tests {
     fn corner_canvas_transform() {
         let mut c = Camera::new(201, 101, PI / 2.0, 0, 1);
         c.set_view_from_matrix(Matrix::rotation_y(PI / 4.0) * Matrix::translation(0.0, -2.0, 5.0));
-        let r = c.ray_for_pixel(100, 50);
+        let r = c.ray_for_pixel(100, 50).unwrap();
 
         assert_eq!(r.origin, Vec4::point(0.0, 2.0, -5.0));
         assert_eq!(r.direction, Vec4::vector(SQRT_2 / 2.0, 0.0, -SQRT_2 / 2.0));
     }
     #[test]
+    fn rays_for_any_sample_count() {
+        let mut c = Camera::new(201, 101, PI / 2.0, 0, 1);
+        let center = c.ray_for_pixel(100, 50).unwrap();
+        for n in [1, 2, 5, 32] {
+            c.set_sampling(n, Halton);
+            let rays = c.rays_for_pixels(100, 50);
+            assert_eq!(rays.len(), n);
+            for r in rays {
+                assert_eq!(r.origin, center.origin);
+                assert!(r.direction.dot(&center.direction) > 0.9999);
+            }
+        }
+    }
+    #[test]
     fn render_func() {
         let w = World::default();
         let mut c = Camera::new(11, 11, PI / 2.0, 0, 1);
@@ -227,7 +644,296 @@ pub mod tests {
         let to = Vec4::point(0.0, 0.0, 0.0);
         let up = Vec4::vector(0.0, 1.0, 0.0);
         c.set_view(from, to, up);
+        c.set_sampling(1, Stratified);
         let image = c.render(&w);
         assert_eq!(image[(5, 5)], Color::new(0.38066, 0.47583, 0.2855));
     }
+    #[test]
+    fn adaptive_sampling_refines_edges_only() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, PI / 2.0, 0, 1);
+        c.set_view(
+            Vec4::point(0.0, 0.0, -5.0),
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(0.0, 1.0, 0.0),
+        );
+        c.set_adaptive_sampling(4, 64, 0.01);
+        let (image, heatmap) = c.render_with_heatmap(&w);
+
+        // the empty background never needs more than the first samples
+        let minimum = 4.0 / 64.0;
+        assert_eq!(image[(0, 0)], Color::black());
+        assert_eq!(heatmap[(0, 0)], Color::new(minimum, minimum, minimum));
+        // the silhouette of the sphere does
+        let edge = (0..11).map(|x| heatmap[(x, 5)].r).fold(0.0, f64::max);
+        assert!(edge > minimum);
+    }
+    #[test]
+    fn same_seed_same_image() {
+        // the jittered area light draws from the same per pixel stream as the camera
+        let mut area = World::default();
+        area.lights.clear();
+        let light = AreaLight::sphere(Vec4::point(-10.0, 10.0, -10.0), 2.0, 2, 2, Color::white());
+        area.add_light(light);
+        for w in [World::default(), area] {
+            let render = |seed, threads| {
+                let mut c = Camera::new(11, 11, PI / 2.0, 0, threads);
+                c.set_view(
+                    Vec4::point(0.0, 0.0, -5.0),
+                    Vec4::point(0.0, 0.0, 0.0),
+                    Vec4::vector(0.0, 1.0, 0.0),
+                );
+                c.set_adaptive_sampling(4, 16, 0.01);
+                c.set_seed(seed);
+                c.render(&w)
+            };
+            let (a, b, c) = (render(7, 1), render(7, 4), render(8, 4));
+            let pixels = |image: &crate::canvas::Canvas| {
+                (0..11 * 11)
+                    .map(|i| image[(i % 11, i / 11)])
+                    .map(|p| (p.r.to_bits(), p.g.to_bits(), p.b.to_bits()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(pixels(&a), pixels(&b));
+            assert_ne!(pixels(&a), pixels(&c));
+        }
+    }
+    #[test]
+    fn thin_lens_rays_meet_on_focal_plane() {
+        let mut c = Camera::new(201, 101, PI / 2.0, 0, 1);
+        c.set_sampling(16, Halton);
+        c.set_depth_of_field(0.5, 5.0);
+        for blades in [0, 6] {
+            c.set_aperture_blades(blades);
+            let rays = c.rays_for_pixels(100, 50);
+            assert!(rays.iter().any(|r| r.origin != Vec4::point(0.0, 0.0, 0.0)));
+            for r in rays {
+                assert!(r.origin.z.abs() < EPSILON);
+                assert!(r.origin.x.hypot(r.origin.y) <= 0.5 + EPSILON);
+                // the whole pixel is 0.05 wide at the focal distance
+                let focus = r.position(-5.0 / r.direction.z);
+                assert!(focus.x.abs() < 0.05 && focus.y.abs() < 0.05);
+            }
+        }
+    }
+    #[test]
+    fn focus_on_first_hit() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, PI / 2.0, 0, 1);
+        c.set_view(
+            Vec4::point(0.0, 0.0, -5.0),
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(0.0, 1.0, 0.0),
+        );
+        assert_eq!(c.focus_on(&w, 0, 0), None);
+        let d = c.focus_on(&w, 5, 5).unwrap();
+        assert!((d - 4.0).abs() < EPSILON);
+        assert_eq!(c.focal_distance, d);
+    }
+    #[test]
+    fn samples_spread_over_the_shutter() {
+        let mut c = Camera::new(201, 101, PI / 2.0, 0, 1);
+        c.set_sampling(8, Halton);
+        c.set_shutter(1.0, 3.0);
+        assert_eq!(c.ray_for_pixel(100, 50).unwrap().time, 1.0);
+        let mut times: Vec<f64> = c.rays_for_pixels(100, 50).iter().map(|r| r.time).collect();
+        times.sort_by(f64::total_cmp);
+        for (i, time) in times.into_iter().enumerate() {
+            let stratum = 1.0 + i as f64 * 0.25;
+            assert!(stratum <= time && time < stratum + 0.25);
+        }
+    }
+    #[test]
+    fn tiles_arrive_for_every_pass() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, PI / 2.0, 0, 2);
+        c.set_view(
+            Vec4::point(0.0, 0.0, -5.0),
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(0.0, 1.0, 0.0),
+        );
+        c.set_sampling(4, Halton);
+        c.set_tiles(4, 2);
+        let tiles = Mutex::new(Vec::new());
+        let (image, heatmap) = c.render_tiles(&w, |tile| tiles.lock().unwrap().push(tile.clone()));
+
+        let tiles = tiles.into_inner().unwrap();
+        assert_eq!(tiles.len(), 9 * 2);
+        assert_eq!(tiles.iter().filter(|t| t.is_final()).count(), 9);
+        assert!(tiles.iter().any(|t| (t.x, t.y, t.width, t.height) == (8, 4, 3, 4)));
+        let mut partial = Canvas::new(11, 11);
+        for tile in tiles.iter().filter(|t| t.is_final()) {
+            tile.write_to(&mut partial);
+        }
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(partial[(x, y)], image[(x, y)]);
+                assert_eq!(heatmap[(x, y)], Color::white());
+            }
+        }
+    }
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Progress>>, Mutex<Vec<String>>);
+
+    impl ProgressObserver for Recorder {
+        fn update(&self, progress: &Progress) {
+            self.0.lock().unwrap().push(*progress);
+        }
+        fn warning(&self, message: &str) {
+            self.1.lock().unwrap().push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn progress_reports_pixels_and_rays() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, PI / 2.0, 0, 2);
+        c.set_sampling(3, Halton);
+        c.set_tiles(4, 1);
+        let recorder = Arc::new(Recorder::default());
+        c.set_progress(Arc::clone(&recorder));
+        c.render(&w);
+
+        let updates = recorder.0.lock().unwrap();
+        assert_eq!(updates.len(), 9);
+        let last = updates.iter().max_by_key(|p| p.pixels_done).unwrap();
+        assert_eq!((last.pixels_done, last.total_pixels), (121, 121));
+        assert_eq!(last.rays, 121 * 3);
+    }
+
+    #[test]
+    fn cancelled_render_returns_partial_image() {
+        let w = World::default();
+        let mut c = Camera::new(11, 11, PI / 2.0, 0, 1);
+        c.set_view(
+            Vec4::point(0.0, 0.0, -5.0),
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(0.0, 1.0, 0.0),
+        );
+        c.set_tiles(4, 1);
+        c.set_progress(Silent);
+        let token = CancellationToken::new();
+        c.set_cancellation(token.clone());
+
+        // the center tile is never rendered
+        let tiles = Mutex::new(0);
+        let (image, _) = c.render_tiles(&w, |_| {
+            *tiles.lock().unwrap() += 1;
+            token.cancel();
+        });
+        assert_eq!(*tiles.lock().unwrap(), 1);
+        assert_eq!(image[(5, 5)], Color::black());
+    }
+
+    #[test]
+    fn interrupted_render_resumes_from_checkpoint() {
+        let w = World::default();
+        let path = std::env::temp_dir().join(format!(
+            "raytracer-resume-{}.checkpoint",
+            std::process::id()
+        ));
+        let camera = |samples, checkpoint: bool| {
+            let mut c = Camera::new(11, 11, PI / 2.0, 0, 2);
+            c.set_view(
+                Vec4::point(0.0, 0.0, -5.0),
+                Vec4::point(0.0, 0.0, 0.0),
+                Vec4::vector(0.0, 1.0, 0.0),
+            );
+            c.set_sampling(samples, Halton);
+            c.set_tiles(4, 1);
+            c.set_progress(Silent);
+            if checkpoint {
+                c.set_checkpoint(&path, Duration::from_secs(3600));
+            }
+            c
+        };
+        let pixels = |image: &Canvas| {
+            (0..11 * 11)
+                .map(|i| image[(i % 11, i / 11)])
+                .map(|p| (p.r.to_bits(), p.g.to_bits(), p.b.to_bits()))
+                .collect::<Vec<_>>()
+        };
+        let expected = camera(4, false).render(&w);
+
+        // stop after the first tile, tiles already in flight on the other
+        // thread still finish, the checkpoint is written on the way out
+        let mut c = camera(4, true);
+        let token = CancellationToken::new();
+        c.set_cancellation(token.clone());
+        let done = Mutex::new(0);
+        c.render_tiles(&w, |tile| {
+            *done.lock().unwrap() += (tile.width * tile.height) as u64;
+            token.cancel();
+        });
+        assert!(path.exists());
+        let done = done.into_inner().unwrap();
+
+        let mut c = camera(4, true);
+        let recorder = Arc::new(Recorder::default());
+        c.set_progress(Arc::clone(&recorder));
+        let image = c.render(&w);
+        assert_eq!(pixels(&image), pixels(&expected));
+        let rays = |r: &Recorder| r.0.lock().unwrap().iter().map(|p| p.rays).max();
+        assert_eq!(rays(&recorder), Some((121 - done) * 4));
+
+        // more samples build on the finished image
+        let mut c = camera(6, true);
+        let recorder = Arc::new(Recorder::default());
+        c.set_progress(Arc::clone(&recorder));
+        c.render(&w);
+        assert_eq!(rays(&recorder), Some(121 * 2));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unusable_checkpoint_starts_over() {
+        let w = World::default();
+        let path = std::env::temp_dir().join(format!(
+            "raytracer-unusable-{}.checkpoint",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"not a checkpoint").unwrap();
+        let mut c = Camera::new(5, 5, PI / 2.0, 0, 2);
+        c.set_sampling(2, Halton);
+        c.set_checkpoint(&path, Duration::from_secs(3600));
+        let recorder = Arc::new(Recorder::default());
+        c.set_progress(Arc::clone(&recorder));
+        c.render(&w);
+
+        let warnings = recorder.1.lock().unwrap().clone();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("can't resume from checkpoint"));
+        let rays = recorder.0.lock().unwrap().iter().map(|p| p.rays).max();
+        assert_eq!(rays, Some(25 * 2));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn spectral_samples_add_up_to_white() {
+        let mut w = World::default();
+        w.clear_shapes();
+        let mut sky = Sphere::new();
+        sky.set_transformation(Matrix::scaling(10.0, 10.0, 10.0));
+        sky.material.ambient = 1.0;
+        sky.material.diffuse = 0.0;
+        sky.material.specular = 0.0;
+        w.add_shape(Arc::new(sky));
+
+        let mut c = Camera::new(3, 3, PI / 2.0, 0, 1);
+        c.set_sampling(256, Halton);
+        c.set_spectral(true);
+        c.set_progress(Silent);
+        for ray in c.rays_for_pixels(1, 1) {
+            let wavelength = ray.wavelength.unwrap();
+            assert!((380.0..780.0).contains(&wavelength));
+        }
+        let image = c.render(&w);
+        for i in 0..9 {
+            let p = image[(i % 3, i / 3)];
+            for channel in [p.r, p.g, p.b] {
+                assert!((channel - 1.0).abs() < 0.05, "{p:?}");
+            }
+        }
+    }
 }
@@ -2,6 +2,7 @@ use image::{ImageBuffer, Rgb, RgbImage};
 
 use crate::color::Color;
 use std::ops::{Index, IndexMut};
+use std::sync::LazyLock;
 
 #[derive(Debug, Clone)]
 pub struct Canvas {
@@ -70,6 +71,108 @@ impl IndexMut<(usize, usize)> for Canvas {
     }
 }
 
+/// Visible range sampled by spectral rendering, in nanometres.
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 780.0;
+
+// lobe of the piecewise gaussian fit, with separate widths left and right of the peak
+fn lobe(wavelength: f64, peak: f64, left: f64, right: f64) -> f64 {
+    let sigma = if wavelength < peak { left } else { right };
+    let x = (wavelength - peak) / sigma;
+    (-0.5 * x * x).exp()
+}
+
+/// CIE 1931 colour matching functions, using the multi-lobe fit of Wyman,
+/// Sloan and Shirley (2013).
+pub fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let w = wavelength;
+    let x = 1.056 * lobe(w, 599.8, 37.9, 31.0) + 0.362 * lobe(w, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(w, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(w, 568.8, 46.9, 40.5) + 0.286 * lobe(w, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(w, 437.0, 11.8, 36.0) + 0.681 * lobe(w, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Linear sRGB, may be negative for colours outside the gamut.
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// average response over the visible range, so that an equal energy
+// spectrum comes out white
+static WHITE: LazyLock<Color> = LazyLock::new(|| {
+    let steps = 4000;
+    let mut sum = Color::black();
+    for i in 0..steps {
+        let t = (i as f64 + 0.5) / steps as f64;
+        let (x, y, z) = cie_xyz(MIN_WAVELENGTH + t * (MAX_WAVELENGTH - MIN_WAVELENGTH));
+        sum += xyz_to_rgb(x, y, z);
+    }
+    sum / steps as f64
+});
+
+/// Weight of a single wavelength sample in the image. Averaged over
+/// wavelengths drawn uniformly from the visible range it is white.
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = cie_xyz(wavelength);
+    let rgb = xyz_to_rgb(x, y, z);
+    let white = *WHITE;
+    Color::new(rgb.r / white.r, rgb.g / white.g, rgb.b / white.b)
+}
+
+// running totals of the samples taken for one pixel, spectral samples are
+// converted to RGB on the way in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PixelStats {
+    pub(crate) sum: Color,
+    pub(crate) luminance_sum: f64,
+    pub(crate) luminance_sq_sum: f64,
+    pub(crate) count: usize,
+}
+
+impl PixelStats {
+    pub(crate) fn new() -> Self {
+        PixelStats {
+            sum: Color::black(),
+            luminance_sum: 0.0,
+            luminance_sq_sum: 0.0,
+            count: 0,
+        }
+    }
+    pub(crate) fn add_sample(&mut self, color: Color, wavelength: Option<f64>) {
+        self.add(wavelength.map_or(color, |w| color * wavelength_to_rgb(w)));
+    }
+    pub(crate) fn add(&mut self, color: Color) {
+        let l = color.luminance();
+        self.sum += color;
+        self.luminance_sum += l;
+        self.luminance_sq_sum += l * l;
+        self.count += 1;
+    }
+    pub(crate) fn mean(&self) -> Color {
+        if self.count == 0 {
+            return Color::black();
+        }
+        self.sum / self.count as f64
+    }
+    pub(crate) fn mean_luminance(&self) -> f64 {
+        self.luminance_sum / self.count as f64
+    }
+    pub(crate) fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        let mean = self.luminance_sum / n;
+        let variance = ((self.luminance_sq_sum - n * mean * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt()
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -111,4 +214,26 @@ pub mod tests {
         assert_eq!(lines[4], "0 0 0 0 0 0 0 128 0 0 0 0 0 0 0");
         assert_eq!(lines[5], "0 0 0 0 0 0 0 0 0 0 0 0 0 0 255");
     }
+
+    #[test]
+    fn uniform_wavelengths_average_to_white() {
+        let n = 1000;
+        let mut sum = Color::black();
+        for i in 0..n {
+            let t = (i as f64 + 0.5) / n as f64;
+            sum += wavelength_to_rgb(MIN_WAVELENGTH + t * (MAX_WAVELENGTH - MIN_WAVELENGTH));
+        }
+        assert_eq!(sum / n as f64, Color::white());
+    }
+
+    #[test]
+    fn wavelengths_map_to_their_hue() {
+        let cases = [(450.0, 2), (530.0, 1), (650.0, 0)];
+        for (wavelength, strongest) in cases {
+            let c = wavelength_to_rgb(wavelength);
+            let channels = [c.r, c.g, c.b];
+            let max = channels.iter().cloned().fold(f64::MIN, f64::max);
+            assert_eq!(channels[strongest], max, "{wavelength}nm: {c:?}");
+        }
+    }
 }
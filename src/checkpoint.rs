@@ -0,0 +1,135 @@
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::canvas::PixelStats;
+use crate::color::Color;
+
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+/// Where and how often `Camera::render` saves the samples taken so far.
+/// A render that finds the file at start continues from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Checkpoint {
+            path: path.into(),
+            interval,
+        }
+    }
+
+    /// Writes the sample sums and counts of every pixel. The file is
+    /// replaced in one step so a render killed while saving leaves the
+    /// previous checkpoint intact.
+    pub(crate) fn save(&self, width: usize, height: usize, stats: &[PixelStats]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 16 + stats.len() * 48);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(height as u64).to_le_bytes());
+        for pixel in stats {
+            for value in [
+                pixel.sum.r,
+                pixel.sum.g,
+                pixel.sum.b,
+                pixel.luminance_sum,
+                pixel.luminance_sq_sum,
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(pixel.count as u64).to_le_bytes());
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// Reads the pixels back, `None` if there is no checkpoint yet. A
+    /// checkpoint of an image with another size is an error.
+    pub(crate) fn load(&self, width: usize, height: usize) -> io::Result<Option<Vec<PixelStats>>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+
+        let mut words = bytes
+            .get(MAGIC.len()..)
+            .filter(|_| bytes.starts_with(MAGIC))
+            .ok_or_else(|| invalid("not a checkpoint file"))?
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()));
+        let size = (words.next(), words.next());
+        if size != (Some(width as u64), Some(height as u64)) {
+            return Err(invalid("checkpoint was made for another image size"));
+        }
+        let words: Vec<u64> = words.collect();
+        if words.len() != width * height * 6 {
+            return Err(invalid("truncated checkpoint"));
+        }
+        Ok(Some(
+            words
+                .chunks_exact(6)
+                .map(|w| PixelStats {
+                    sum: Color::new(
+                        f64::from_bits(w[0]),
+                        f64::from_bits(w[1]),
+                        f64::from_bits(w[2]),
+                    ),
+                    luminance_sum: f64::from_bits(w[3]),
+                    luminance_sq_sum: f64::from_bits(w[4]),
+                    count: w[5] as usize,
+                })
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn checkpoint(name: &str) -> Checkpoint {
+        let path = std::env::temp_dir().join(format!(
+            "raytracer-{name}-{}.checkpoint",
+            std::process::id()
+        ));
+        Checkpoint::new(path, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let c = checkpoint("round-trip");
+        assert_eq!(c.load(2, 1).unwrap(), None);
+
+        let mut pixel = PixelStats::new();
+        pixel.add(Color::new(0.1, 0.2, 0.3));
+        pixel.add(Color::new(1.0, 0.5, 0.25));
+        let stats = [pixel, PixelStats::new()];
+        c.save(2, 1, &stats).unwrap();
+        assert!(c.path.exists());
+        assert_eq!(c.load(2, 1).unwrap(), Some(stats.to_vec()));
+
+        let error = c.load(1, 2).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        fs::remove_file(&c.path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let c = checkpoint("garbage");
+        fs::write(&c.path, b"P3\n1 1\n255\n0 0 0\n").unwrap();
+        assert_eq!(c.load(1, 1).unwrap_err().kind(), ErrorKind::InvalidData);
+        fs::remove_file(&c.path).unwrap();
+    }
+}
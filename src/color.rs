@@ -111,6 +111,10 @@ impl Color {
             b: 0.75,
         }
     }
+    /// Perceived brightness (Rec. 709 weights).
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
     pub fn to_rgb_u8(self) -> (u8, u8, u8) {
         (
             (self.r.clamp(0.0, 1.0) * 255.0f64).round() as u8,
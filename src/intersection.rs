@@ -1,4 +1,7 @@
+use crate::color::Color;
+use crate::material::Material;
 use crate::math::{ApproxEq, EPSILON};
+use crate::matrix::SqMatrix;
 use crate::ray::Ray;
 use crate::shapes::Shape;
 use crate::vec4::Vec4;
@@ -6,7 +9,11 @@ use std::cmp::Ordering;
 
 pub struct Computations<'a> {
     pub object: &'a dyn Shape,
+    /// Material used for shading, the object's own unless an `Instance` overrides it.
+    pub material: &'a Material,
     pub point: Vec4,
+    /// Hit point in the object space of `object`, walking through all parent groups.
+    pub object_point: Vec4,
     pub eyev: Vec4,
     pub normalv: Vec4,
     pub inside: bool,
@@ -15,6 +22,15 @@ pub struct Computations<'a> {
     pub n1: f64,
     pub n2: f64,
     pub under_point: Vec4,
+    /// Time of the ray that hit, secondary rays are cast at the same time.
+    pub time: f64,
+    /// Share of the light from the hit that reaches the ray origin, less than
+    /// white when the ray travels inside an absorbing material.
+    pub transmittance: Color,
+    /// Wavelength of the ray that hit, secondary rays keep it.
+    pub wavelength: Option<f64>,
+    /// Bounces of the ray that hit, secondary rays have one more.
+    pub bounces: usize,
 }
 impl<'a> Computations<'a> {
     pub fn new(
@@ -36,7 +52,9 @@ impl<'a> Computations<'a> {
         let under_point = point - normalv * EPSILON;
         Self {
             object,
+            material: object.material(),
             point,
+            object_point: point,
             eyev,
             normalv,
             inside,
@@ -45,6 +63,10 @@ impl<'a> Computations<'a> {
             n1: n1,
             n2: n2,
             under_point,
+            time: 0.0,
+            transmittance: Color::white(),
+            wavelength: None,
+            bounces: 0,
         }
     }
     pub fn object(&self) -> &'a dyn Shape {
@@ -74,11 +96,46 @@ pub struct Intersection<'a> {
     pub object: &'a dyn Shape,
     pub u: Option<f64>,
     pub v: Option<f64>,
+    /// Transforms world space into the space of the parent of `object`,
+    /// `None` if the object is not nested inside a group.
+    pub world_to_parent: Option<SqMatrix<4>>,
+    /// Material override of the closest enclosing `Instance`.
+    pub material: Option<&'a Material>,
+}
+
+impl<'a> Intersection<'a> {
+    /// Sets the material override unless a closer instance already did.
+    pub fn with_material(mut self, material: &'a Material) -> Self {
+        self.material.get_or_insert(material);
+        self
+    }
 }
 
 impl Intersection<'_> {
-    pub fn new(t: f64, object: &dyn Shape, u: Option<f64>, v: Option<f64>) -> Intersection {
-        Intersection { t, object, u, v }
+    pub fn new(t: f64, object: &dyn Shape, u: Option<f64>, v: Option<f64>) -> Intersection<'_> {
+        Intersection {
+            t,
+            object,
+            u,
+            v,
+            world_to_parent: None,
+            material: None,
+        }
+    }
+
+    /// Material the hit is shaded with.
+    pub fn material(&self) -> &Material {
+        self.material.unwrap_or_else(|| self.object.material())
+    }
+
+    /// Records that the hit was found inside a parent with the inverse transform `parent_inverse`.
+    /// Called by groups on the way back up, so the outermost parent is applied last.
+    pub fn with_parent(mut self, parent_inverse: &SqMatrix<4>) -> Self {
+        self.world_to_parent = Some(match self.world_to_parent {
+            Some(m) => &m * parent_inverse,
+            None => *parent_inverse,
+        });
+        self
     }
 
     pub fn hit<'a>(int_list: &'a [Intersection<'a>]) -> Option<&'a Intersection<'a>> {
@@ -91,7 +148,7 @@ impl Intersection<'_> {
         &self,
         ray: &Ray,
         int_list: &'a [Intersection<'a>],
-    ) -> Computations {
+    ) -> Computations<'_> {
         let point = ray.position(self.t);
         let mut comps = Computations::new(
             self.object,
@@ -102,26 +159,40 @@ impl Intersection<'_> {
             0.0,
             0.0,
         );
-        let mut container: Vec<&dyn Shape> = Vec::new();
+        comps.object_point = self.object.world_to_object(&point, self);
+        comps.material = self.material();
+        comps.time = ray.time;
+        comps.wavelength = ray.wavelength;
+        comps.bounces = ray.bounces;
+        // instances of the same shape share its id, they differ in their transform
+        let mut container: Vec<&Intersection> = Vec::new();
 
         for i in int_list {
             if std::ptr::eq(i, self) {
                 comps.n1 = container
                     .last()
-                    .map_or(1.0, |s| s.material().refractive_index);
+                    .map_or(1.0, |x| x.material().refractive_index_at(ray.wavelength));
+                // the ray has been inside the innermost container since it
+                // entered it, or since its origin if that lies inside
+                if let Some(entry) = container.last() {
+                    let distance = (self.t - entry.t.max(0.0)) * ray.direction.magnitude();
+                    comps.transmittance = entry.material().transmittance(distance);
+                }
             }
-            match container.iter().position(|x| x.id() == i.object.id()) {
+            match container.iter().position(|x| {
+                x.object.id() == i.object.id() && x.world_to_parent == i.world_to_parent
+            }) {
                 Some(index) => {
                     container.remove(index);
                 }
                 None => {
-                    container.push(i.object);
+                    container.push(i);
                 }
             }
             if std::ptr::eq(i, self) {
                 comps.n2 = container
                     .last()
-                    .map_or(1.0, |s| s.material().refractive_index);
+                    .map_or(1.0, |x| x.material().refractive_index_at(ray.wavelength));
                 break;
             }
         }
@@ -152,6 +223,7 @@ impl Ord for Intersection<'_> {
 pub mod tests {
     use std::f64::consts::SQRT_2;
 
+    use crate::material::Dispersion;
     use crate::{Sphere, shapes::plane::Plane};
 
     use super::*;
@@ -203,6 +275,51 @@ pub mod tests {
         assert_eq!(comps.normalv, Vec4::vector(0.0, 0.0, -1.0));
     }
 
+    #[test]
+    fn transmittance_follows_the_distance_inside() {
+        let mut s = Sphere::new();
+        s.material.absorption = Color::new(0.0, 0.5, 1.0);
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let cases = [
+            // from outside the ray has travelled through air
+            (r, 1.0, 0, Color::white()),
+            (r, 1.0, 1, Color::new(1.0, (-1.0f64).exp(), (-2.0f64).exp())),
+            (r, 3.0, 1, Color::new(1.0, (-3.0f64).exp(), (-6.0f64).exp())),
+            // starting inside only the way from the origin counts
+            (
+                Ray::new(0.0, 0.0, 0.5, 0.0, 0.0, 1.0),
+                1.0,
+                1,
+                Color::new(1.0, (-0.25f64).exp(), (-0.5f64).exp()),
+            ),
+        ];
+        for (ray, density, hit, expected) in cases {
+            s.material.density = density;
+            let xs = s.intersect(&ray);
+            let comps = xs[hit].prepare_computations(&ray, &xs);
+            assert_eq!(comps.transmittance, expected);
+        }
+    }
+
+    #[test]
+    fn refractive_index_follows_the_wavelength() {
+        let mut s = Sphere::glas(1.5);
+        s.material.dispersion = Some(Dispersion::BK7);
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let cases = [
+            (None, 1.5),
+            (Some(450.0), Dispersion::BK7.index(450.0)),
+            (Some(650.0), Dispersion::BK7.index(650.0)),
+        ];
+        for (wavelength, expected) in cases {
+            let r = r.with_wavelength(wavelength);
+            let xs = s.intersect(&r);
+            let comps = xs[0].prepare_computations(&r, &xs);
+            assert_eq!((comps.n1, comps.n2), (1.0, expected));
+            assert_eq!(comps.wavelength, wavelength);
+        }
+    }
+
     #[test]
     fn precompute_reflection_vec() {
         let s = Plane::new();
@@ -1,5 +1,3 @@
-pub const SAMPLES_PER_PIXEL: usize = 32;
-
 pub mod camera;
 pub mod canvas;
 pub mod color;
@@ -9,11 +7,17 @@ pub mod material;
 pub mod math;
 pub mod matrix;
 pub mod ray;
+pub mod sampling;
 pub mod shapes;
 pub use shapes::sphere::Sphere;
+pub mod animation;
 pub mod bounds;
+pub mod bvh;
+pub mod checkpoint;
 pub mod obj_parser;
 pub mod patterns;
+pub mod progress;
+pub mod projection;
 pub mod transform;
 pub mod vec4;
 pub mod world;
@@ -1,5 +1,44 @@
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+use rand::{Rng, RngCore};
+
 use crate::{color::Color, vec4::Vec4};
 
+/// A single position on a light as seen from a shaded point.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// Normalized vector from the shaded point towards the light.
+    pub lightv: Vec4,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f64,
+    /// Light arriving at the shaded point from this sample.
+    pub intensity: Color,
+}
+
+impl LightSample {
+    pub fn towards(position: &Vec4, point: &Vec4, intensity: Color) -> Self {
+        let v = *position - *point;
+        let distance = v.magnitude();
+        Self {
+            lightv: v / distance,
+            distance,
+            intensity,
+        }
+    }
+}
+
+pub trait Light: Debug + Sync + Send {
+    /// Unattenuated color of the light, used for the ambient term.
+    fn intensity(&self) -> Color;
+    /// Samples used for shading and shadow rays at `point`, lights that
+    /// sample randomly draw from `rng`.
+    fn samples_at(&self, point: &Vec4, rng: &mut dyn RngCore) -> Vec<LightSample>;
+    /// Moves the light so that it is centered on `position`, lights without a
+    /// position ignore it.
+    fn set_position(&mut self, _position: Vec4) {}
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PointLight {
     pub position: Vec4,
@@ -15,5 +54,309 @@ impl PointLight {
     }
 }
 
+impl Light for PointLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+    fn samples_at(&self, point: &Vec4, _rng: &mut dyn RngCore) -> Vec<LightSample> {
+        vec![LightSample::towards(&self.position, point, self.intensity)]
+    }
+    fn set_position(&mut self, position: Vec4) {
+        self.position = position;
+    }
+}
+
+/// Light infinitely far away, all rays arrive parallel along `direction`.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction: Vec4,
+    pub intensity: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec4, intensity: Color) -> Self {
+        Self {
+            direction: direction.norm(),
+            intensity,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+    fn samples_at(&self, _point: &Vec4, _rng: &mut dyn RngCore) -> Vec<LightSample> {
+        vec![LightSample {
+            lightv: -self.direction,
+            distance: f64::INFINITY,
+            intensity: self.intensity,
+        }]
+    }
+}
+
+/// Point light restricted to a cone around `direction`. Full intensity inside
+/// `inner_angle`, smooth falloff to zero at `outer_angle` (both half angles in radians).
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    pub position: Vec4,
+    pub direction: Vec4,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub intensity: Color,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec4,
+        direction: Vec4,
+        inner_angle: f64,
+        outer_angle: f64,
+        intensity: Color,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.norm(),
+            inner_angle,
+            outer_angle: outer_angle.max(inner_angle),
+            intensity,
+        }
+    }
+    pub fn falloff(&self, point: &Vec4) -> f64 {
+        let cos_angle = (*point - self.position).norm().dot(&self.direction);
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+        if cos_angle >= cos_inner {
+            1.0
+        } else if cos_angle <= cos_outer {
+            0.0
+        } else {
+            let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+    fn samples_at(&self, point: &Vec4, _rng: &mut dyn RngCore) -> Vec<LightSample> {
+        let intensity = self.intensity * self.falloff(point);
+        vec![LightSample::towards(&self.position, point, intensity)]
+    }
+    fn set_position(&mut self, position: Vec4) {
+        self.position = position;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AreaShape {
+    /// Parallelogram spanned by `uvec` and `vvec` starting at `corner`.
+    Rect {
+        corner: Vec4,
+        uvec: Vec4,
+        vvec: Vec4,
+    },
+    /// Surface of a sphere, `u` runs from pole to pole and `v` around the equator.
+    Sphere { center: Vec4, radius: f64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AreaLight {
+    pub shape: AreaShape,
+    pub usteps: usize,
+    pub vsteps: usize,
+    pub intensity: Color,
+    pub jitter: bool,
+}
+
+impl AreaLight {
+    pub fn rect(
+        corner: Vec4,
+        full_uvec: Vec4,
+        usteps: usize,
+        full_vvec: Vec4,
+        vsteps: usize,
+        intensity: Color,
+    ) -> Self {
+        Self {
+            shape: AreaShape::Rect {
+                corner,
+                uvec: full_uvec,
+                vvec: full_vvec,
+            },
+            usteps,
+            vsteps,
+            intensity,
+            jitter: true,
+        }
+    }
+    pub fn sphere(center: Vec4, radius: f64, usteps: usize, vsteps: usize, intensity: Color) -> Self {
+        Self {
+            shape: AreaShape::Sphere { center, radius },
+            usteps,
+            vsteps,
+            intensity,
+            jitter: true,
+        }
+    }
+    pub fn sample_count(&self) -> usize {
+        self.usteps * self.vsteps
+    }
+    pub fn center(&self) -> Vec4 {
+        match self.shape {
+            AreaShape::Rect { corner, uvec, vvec } => corner + uvec * 0.5 + vvec * 0.5,
+            AreaShape::Sphere { center, .. } => center,
+        }
+    }
+    /// Point inside the cell (`u`, `v`) of the light, `du` and `dv` are the offsets within the cell.
+    pub fn point_on_light(&self, u: usize, v: usize, du: f64, dv: f64) -> Vec4 {
+        let su = (u as f64 + du) / self.usteps as f64;
+        let sv = (v as f64 + dv) / self.vsteps as f64;
+        match self.shape {
+            AreaShape::Rect { corner, uvec, vvec } => corner + uvec * su + vvec * sv,
+            AreaShape::Sphere { center, radius } => {
+                let z = 1.0 - 2.0 * su;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let phi = 2.0 * PI * sv;
+                center + Vec4::vector(r * phi.cos(), r * phi.sin(), z) * radius
+            }
+        }
+    }
+    /// One stratified sample per cell, jittered within the cell unless `jitter` is disabled.
+    pub fn sample_points(&self, rng: &mut dyn RngCore) -> Vec<Vec4> {
+        let mut points = Vec::with_capacity(self.sample_count());
+        for v in 0..self.vsteps {
+            for u in 0..self.usteps {
+                let (du, dv) = if self.jitter {
+                    (rng.random::<f64>(), rng.random::<f64>())
+                } else {
+                    (0.5, 0.5)
+                };
+                points.push(self.point_on_light(u, v, du, dv));
+            }
+        }
+        points
+    }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+    fn samples_at(&self, point: &Vec4, rng: &mut dyn RngCore) -> Vec<LightSample> {
+        self.sample_points(rng)
+            .iter()
+            .map(|p| LightSample::towards(p, point, self.intensity))
+            .collect()
+    }
+    fn set_position(&mut self, position: Vec4) {
+        let offset = position - self.center();
+        match &mut self.shape {
+            AreaShape::Rect { corner, .. } => *corner += offset,
+            AreaShape::Sphere { center, .. } => *center += offset,
+        }
+    }
+}
+
 #[cfg(test)]
-pub mod tests {}
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_light_samples_cell_centers() {
+        let mut light = AreaLight::rect(
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(2.0, 0.0, 0.0),
+            4,
+            Vec4::vector(0.0, 0.0, 1.0),
+            2,
+            Color::white(),
+        );
+        light.jitter = false;
+        assert_eq!(light.sample_count(), 8);
+        assert_eq!(light.center(), Vec4::point(1.0, 0.0, 0.5));
+
+        let points = light.sample_points(&mut rand::rng());
+        assert_eq!(points.len(), 8);
+        assert_eq!(points[0], Vec4::point(0.25, 0.0, 0.25));
+        assert_eq!(points[2], Vec4::point(1.25, 0.0, 0.25));
+        assert_eq!(points[7], Vec4::point(1.75, 0.0, 0.75));
+    }
+
+    #[test]
+    fn jittered_samples_stay_in_their_cell() {
+        let light = AreaLight::rect(
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(2.0, 0.0, 0.0),
+            4,
+            Vec4::vector(0.0, 0.0, 1.0),
+            2,
+            Color::white(),
+        );
+        for (i, p) in light.sample_points(&mut rand::rng()).iter().enumerate() {
+            let (u, v) = ((i % 4) as f64, (i / 4) as f64);
+            assert!(p.x >= u * 0.5 && p.x <= (u + 1.0) * 0.5);
+            assert!(p.z >= v * 0.5 && p.z <= (v + 1.0) * 0.5);
+        }
+    }
+
+    #[test]
+    fn directional_light_has_no_distance_limit() {
+        let light = DirectionalLight::new(Vec4::vector(0.0, -2.0, 0.0), Color::white());
+        let samples = light.samples_at(&Vec4::point(3.0, 0.0, -7.0), &mut rand::rng());
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].lightv, Vec4::vector(0.0, 1.0, 0.0));
+        assert_eq!(samples[0].distance, f64::INFINITY);
+    }
+
+    #[test]
+    fn spot_light_falls_off_between_cones() {
+        let light = SpotLight::new(
+            Vec4::point(0.0, 10.0, 0.0),
+            Vec4::vector(0.0, -1.0, 0.0),
+            PI / 8.0,
+            PI / 4.0,
+            Color::white(),
+        );
+        assert_eq!(light.falloff(&Vec4::point(0.0, 0.0, 0.0)), 1.0);
+        assert_eq!(light.falloff(&Vec4::point(11.0, 0.0, 0.0)), 0.0);
+        assert_eq!(light.falloff(&Vec4::point(0.0, 20.0, 0.0)), 0.0);
+
+        let mid = light.falloff(&Vec4::point(6.0, 0.0, 0.0));
+        assert!(mid > 0.0 && mid < 1.0);
+        let samples = light.samples_at(&Vec4::point(6.0, 0.0, 0.0), &mut rand::rng());
+        assert_eq!(samples[0].intensity, Color::white() * mid);
+    }
+
+    #[test]
+    fn sphere_light_samples_lie_on_surface() {
+        let light = AreaLight::sphere(Vec4::point(1.0, 2.0, 3.0), 0.5, 4, 4, Color::white());
+        for p in light.sample_points(&mut rand::rng()) {
+            assert!(((p - light.center()).magnitude() - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn set_position_moves_the_light_center() {
+        let mut rect = AreaLight::rect(
+            Vec4::point(0.0, 0.0, 0.0),
+            Vec4::vector(2.0, 0.0, 0.0),
+            2,
+            Vec4::vector(0.0, 0.0, 2.0),
+            2,
+            Color::white(),
+        );
+        let mut sphere = AreaLight::sphere(Vec4::point(1.0, 2.0, 3.0), 0.5, 4, 4, Color::white());
+        let target = Vec4::point(5.0, 5.0, 5.0);
+        rect.set_position(target);
+        sphere.set_position(target);
+        assert_eq!(rect.center(), target);
+        assert_eq!(sphere.center(), target);
+
+        let mut directional = DirectionalLight::new(Vec4::vector(0.0, -1.0, 0.0), Color::white());
+        directional.set_position(target);
+        assert_eq!(directional.direction, Vec4::vector(0.0, -1.0, 0.0));
+    }
+}
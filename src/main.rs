@@ -7,6 +7,7 @@ use raytracer::{
     matrix::Matrix,
     obj_parser::Parser,
     patterns::{Pattern, checker::Checker},
+    sampling::CorrelatedMultiJitter,
     shapes::{Shape, cube::Cube, plane::Plane},
     vec4::Vec4,
     world::World,
@@ -69,7 +70,13 @@ fn main() {
         Vec4::point(0.0, 1.0, 0.0),
         Vec4::vector(0.0, 1.0, 0.0),
     );
+    // samples per pixel, e.g. `raytracer 1` for a quick preview
+    let samples = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(camera.samples_per_pixel);
+    camera.set_sampling(samples, CorrelatedMultiJitter);
 
     let image = camera.render(&world);
-    image.save("teapot-super-16.png");
+    image.save(&format!("teapot-super-{}.png", camera.samples_per_pixel));
 }
@@ -1,6 +1,62 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
-use crate::{color::Color, light::PointLight, patterns::Pattern, shapes::Shape, vec4::Vec4};
+use rand::{Rng, RngCore};
+
+use crate::{
+    color::Color,
+    light::{Light, LightSample},
+    patterns::Pattern,
+    vec4::Vec4,
+};
+
+// keeps the GGX distribution finite for perfectly smooth surfaces
+const MIN_ALPHA: f64 = 1e-3;
+
+/// Reflectance model used by `Material::lighting`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shading {
+    /// `diffuse`, `specular` and `shininess` terms.
+    Phong,
+    /// Microfacet model driven by `metallic` and `roughness`, with a GGX
+    /// distribution, Smith geometry term and Fresnel-Schlick reflectance.
+    /// The base colour is `color` or the pattern.
+    Pbr,
+}
+
+/// Refractive index that changes with the wavelength, the coefficients use
+/// wavelengths in micrometres as is customary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+
+    /// Refractive index at `wavelength` nanometres.
+    pub fn index(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Material {
@@ -13,6 +69,24 @@ pub struct Material {
     pub reflective: f64,
     pub transparency: f64,
     pub refractive_index: f64,
+    /// Replaces `refractive_index` for rays that carry a wavelength.
+    pub dispersion: Option<Dispersion>,
+    /// Beer-Lambert absorption per unit distance for each channel, light
+    /// passing through a transparent material is tinted by the complement.
+    pub absorption: Color,
+    /// Scales `absorption`, thicker media absorb more over the same distance.
+    pub density: f64,
+    /// Shapes that don't cast shadows let all light through to the shapes
+    /// behind them.
+    pub casts_shadow: bool,
+    pub shading: Shading,
+    pub metallic: f64,
+    /// Microfacet roughness, also scatters reflected and refracted rays
+    /// around the mirror direction. Zero gives perfect mirrors and clear glass.
+    pub roughness: f64,
+    /// Rays averaged for rough reflection and refraction where a camera ray
+    /// hits, hits further down trace a single ray.
+    pub glossy_samples: usize,
 }
 
 impl Material {
@@ -27,6 +101,14 @@ impl Material {
             reflective: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            dispersion: None,
+            absorption: Color::black(),
+            density: 1.0,
+            casts_shadow: true,
+            shading: Shading::Phong,
+            metallic: 0.0,
+            roughness: 0.0,
+            glossy_samples: 8,
         }
     }
     pub fn new(
@@ -47,6 +129,26 @@ impl Material {
             reflective,
             transparency: 0.0,
             refractive_index: 1.0,
+            dispersion: None,
+            absorption: Color::black(),
+            density: 1.0,
+            casts_shadow: true,
+            shading: Shading::Phong,
+            metallic: 0.0,
+            roughness: 0.0,
+            glossy_samples: 8,
+        }
+    }
+    /// Physically based material in the metallic/roughness workflow. It
+    /// mirrors its surroundings by `reflectance`, which follows from
+    /// `metallic` and `roughness`, `reflective` is not used.
+    pub fn pbr(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        Self {
+            color: base_color,
+            shading: Shading::Pbr,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            ..Material::default()
         }
     }
     pub fn glas() -> Self {
@@ -62,55 +164,202 @@ impl Material {
     pub fn set_pattern(&mut self, pattern: impl Pattern + 'static) {
         self.pattern = Some(Arc::new(pattern));
     }
+    /// Shading with the material's model. Diffuse and specular are averaged
+    /// over `samples`, the light's samples seen from the shaded point, each
+    /// scaled by the share of it that gets past the shapes in between.
+    /// Patterns are evaluated at `object_point`, the point in object space.
     pub fn lighting(
         material: &Material,
-        object: &dyn Shape,
-        light: &PointLight,
-        point: &Vec4,
+        object_point: &Vec4,
+        light: &dyn Light,
         eyev: &Vec4,
         normalv: &Vec4,
-        in_shadow: bool,
+        samples: &[(LightSample, Color)],
     ) -> Color {
-        let mut effective_color = match material.pattern {
-            Some(ref p) => p.pattern_at(object, point),
-            None => material.color,
-        };
-        effective_color = effective_color * light.intensity;
-        let lightv = (light.position - *point).norm();
-        let ambient = effective_color * material.ambient;
-        let light_dot_normal = lightv.dot(&normalv);
-
-        if in_shadow {
+        let effective_color = material.effective_color(object_point);
+        let ambient = effective_color * light.intensity() * material.ambient;
+        if samples.is_empty() {
             return ambient;
         }
+        let mut direct = Color::black();
+        for (sample, transmittance) in samples {
+            if *transmittance != Color::black() {
+                direct += material.direct_lighting(effective_color, sample, eyev, normalv)
+                    * *transmittance;
+            }
+        }
+        ambient + direct / samples.len() as f64
+    }
+    /// Refractive index for light of `wavelength` nanometres.
+    pub fn refractive_index_at(&self, wavelength: Option<f64>) -> f64 {
+        match (&self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index(wavelength),
+            _ => self.refractive_index,
+        }
+    }
+    /// Share of the light left after travelling `distance` through the
+    /// material.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let optical_depth = self.absorption * (self.density * distance);
+        Color::new(
+            (-optical_depth.r).exp(),
+            (-optical_depth.g).exp(),
+            (-optical_depth.b).exp(),
+        )
+    }
+    /// Share of the light a `Pbr` material mirrors towards the eye, tinted
+    /// by the base colour for metals. It follows the Fresnel term, except
+    /// that rough surfaces spread the grazing peak and stay below
+    /// `1 - roughness`. `Phong` materials mirror `reflective` of it.
+    pub fn reflectance(&self, object_point: &Vec4, eyev: &Vec4, normalv: &Vec4) -> Color {
+        match self.shading {
+            Shading::Phong => Color::white() * self.reflective,
+            Shading::Pbr => {
+                let f0 = self.f0(self.effective_color(object_point));
+                let peak = 1.0 - self.roughness;
+                let f90 = Color::new(f0.r.max(peak), f0.g.max(peak), f0.b.max(peak));
+                let cos = eyev.dot(normalv).clamp(0.0, 1.0);
+                f0 + (f90 - f0) * (1.0 - cos).powi(5)
+            }
+        }
+    }
+    // reflectance at normal incidence, 4% for dielectrics
+    fn f0(&self, base_color: Color) -> Color {
+        Color::new(0.04, 0.04, 0.04) * (1.0 - self.metallic) + base_color * self.metallic
+    }
+    fn effective_color(&self, object_point: &Vec4) -> Color {
+        match self.pattern {
+            Some(ref p) => p.pattern_at_object(object_point),
+            None => self.color,
+        }
+    }
+    fn direct_lighting(
+        &self,
+        effective_color: Color,
+        sample: &LightSample,
+        eyev: &Vec4,
+        normalv: &Vec4,
+    ) -> Color {
+        match self.shading {
+            Shading::Phong => self.phong(effective_color, sample, eyev, normalv),
+            Shading::Pbr => self.microfacet(effective_color, sample, eyev, normalv),
+        }
+    }
+    fn phong(
+        &self,
+        effective_color: Color,
+        sample: &LightSample,
+        eyev: &Vec4,
+        normalv: &Vec4,
+    ) -> Color {
+        let light_dot_normal = sample.lightv.dot(normalv);
+        if light_dot_normal < 0.0 {
+            return Color::black();
+        }
+        let diffuse = effective_color * sample.intensity * self.diffuse * light_dot_normal;
+
+        let reflectv = (-sample.lightv).reflect(normalv);
+        let reflect_dot_eye = reflectv.dot(eyev);
 
-        let (diffuse, specular) = if light_dot_normal < 0.0 {
-            (Color::black(), Color::black())
+        let specular = if reflect_dot_eye <= 0.0 {
+            Color::black()
         } else {
-            let diffuse = effective_color * material.diffuse * light_dot_normal;
-
-            let reflectv = (-lightv).reflect(&normalv);
-            let reflect_dot_eye = reflectv.dot(&eyev);
-
-            let specular = if reflect_dot_eye <= 0.0 {
-                Color::black()
-            } else {
-                let factor = reflect_dot_eye.powf(material.shininess);
-                light.intensity * material.specular * factor
-            };
-            (diffuse, specular)
+            let factor = reflect_dot_eye.powf(self.shininess);
+            sample.intensity * self.specular * factor
         };
-        ambient + diffuse + specular
+        diffuse + specular
+    }
+    // Cook-Torrance BRDF times the cosine term. Light intensities are scaled
+    // by π, so a white Lambertian surface facing a white light is as bright
+    // as with Phong and `diffuse` 1.
+    fn microfacet(
+        &self,
+        base_color: Color,
+        sample: &LightSample,
+        eyev: &Vec4,
+        normalv: &Vec4,
+    ) -> Color {
+        let n_dot_l = sample.lightv.dot(normalv);
+        if n_dot_l <= 0.0 {
+            return Color::black();
+        }
+        let n_dot_v = eyev.dot(normalv).max(1e-4);
+        let halfway = (sample.lightv + *eyev).norm();
+        let n_dot_h = normalv.dot(&halfway).max(0.0);
+        let v_dot_h = eyev.dot(&halfway).max(0.0);
+
+        let d = ggx_distribution(n_dot_h, self.roughness);
+        let g = smith_geometry(n_dot_v, n_dot_l, self.roughness);
+        let f = fresnel_schlick(self.f0(base_color), v_dot_h);
+        let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l));
+        // metals have no diffuse part, whatever isn't reflected is absorbed
+        let diffuse = (Color::white() - f) * base_color * ((1.0 - self.metallic) / PI);
+        (diffuse + specular) * sample.intensity * (n_dot_l * PI)
     }
 }
 
+/// GGX (Trowbridge-Reitz) normal distribution, with alpha = roughness².
+pub fn ggx_distribution(n_dot_h: f64, roughness: f64) -> f64 {
+    let a2 = (roughness * roughness).max(MIN_ALPHA).powi(2);
+    let denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * denom * denom)
+}
+
+/// Microfacet normal drawn from the GGX distribution around `normal`, the
+/// rougher the surface the further it strays.
+pub fn sample_ggx(normal: &Vec4, roughness: f64, rng: &mut dyn RngCore) -> Vec4 {
+    let alpha = (roughness * roughness).max(MIN_ALPHA);
+    let (u1, u2) = (rng.random::<f64>(), rng.random::<f64>());
+    let theta = (alpha * (u1 / (1.0 - u1)).sqrt()).atan();
+    let phi = 2.0 * PI * u2;
+
+    let helper = if normal.x.abs() > 0.9 {
+        Vec4::vector(0.0, 1.0, 0.0)
+    } else {
+        Vec4::vector(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(normal).norm();
+    let bitangent = normal.cross(&tangent);
+    (tangent * (theta.sin() * phi.cos())
+        + bitangent * (theta.sin() * phi.sin())
+        + *normal * theta.cos())
+    .norm()
+}
+
+/// Smith shadowing-masking with the Schlick-GGX approximation for direct
+/// lighting.
+pub fn smith_geometry(n_dot_v: f64, n_dot_l: f64, roughness: f64) -> f64 {
+    let k = (roughness + 1.0).powi(2) / 8.0;
+    let g1 = |cos: f64| cos / (cos * (1.0 - k) + k);
+    g1(n_dot_v) * g1(n_dot_l)
+}
+
+/// Schlick's approximation of the Fresnel reflectance, `f0` at normal
+/// incidence rising to white at grazing angles.
+pub fn fresnel_schlick(f0: Color, cos: f64) -> Color {
+    f0 + (Color::white() - f0) * (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
 #[cfg(test)]
 pub mod tests {
-    use crate::{Sphere, patterns::stripe_pattern::StripePattern};
+    use crate::{
+        light::{AreaLight, DirectionalLight, PointLight, SpotLight},
+        math::EPSILON,
+        patterns::stripe_pattern::StripePattern,
+    };
 
     use super::*;
     use std::f64::consts::SQRT_2;
 
+    // samples of `light` at `point`, all letting through `transmittance`
+    fn visible(light: &dyn Light, point: &Vec4, transmittance: Color) -> Vec<(LightSample, Color)> {
+        let samples = light.samples_at(point, &mut rand::rng());
+        samples
+            .into_iter()
+            .map(|sample| (sample, transmittance))
+            .collect()
+    }
+
     #[test]
     pub fn lighting_with_eye_offset_45_degrees() {
         let m = Material::default();
@@ -118,8 +367,14 @@ pub mod tests {
         let eyev = Vec4::vector(0.0, SQRT_2 / 2.0, -SQRT_2 / 2.0);
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
-        let s = Box::new(Sphere::new());
-        let result = Material::lighting(&m, s.as_ref(), &light, &position, &eyev, &normalv, false);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
 
         let expected = Color::new(1.0, 1.0, 1.0);
         assert_eq!(result, expected);
@@ -133,8 +388,14 @@ pub mod tests {
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let s = Box::new(Sphere::new());
-        let result = Material::lighting(&m, s.as_ref(), &light, &position, &eyev, &normalv, false);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
 
         let expected = Color::new(0.7364, 0.7364, 0.7364);
         assert_eq!(result, expected);
@@ -149,8 +410,14 @@ pub mod tests {
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
 
-        let s = Box::new(Sphere::new());
-        let result = Material::lighting(&m, s.as_ref(), &light, &position, &eyev, &normalv, false);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
 
         let expected = Color::new(1.6364, 1.6364, 1.6364);
         assert_eq!(result, expected);
@@ -163,8 +430,14 @@ pub mod tests {
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
 
-        let s = Box::new(Sphere::new());
-        let result = Material::lighting(&m, s.as_ref(), &light, &position, &eyev, &normalv, false);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
 
         let expected = Color::new(0.1, 0.1, 0.1);
         assert_eq!(result, expected);
@@ -177,11 +450,302 @@ pub mod tests {
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
 
-        let s = Box::new(Sphere::new());
-        let result = Material::lighting(&m, s.as_ref(), &light, &position, &eyev, &normalv, false);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
         let expected = Color::new(0.1, 0.1, 0.1);
         assert_eq!(result, expected);
     }
+    #[test]
+    fn lighting_uses_light_intensity_fraction() {
+        let m = Material::default();
+        let position = Vec4::point(0.0, 0.0, -1.0);
+        let eyev = Vec4::vector(0.0, 0.0, -1.0);
+        let normalv = Vec4::vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(Vec4::point(0.0, 0.0, -10.0), Color::white());
+        for (intensity, expected) in [(1.0, 1.9), (0.5, 1.0), (0.0, 0.1)] {
+            let intensity = Color::white() * intensity;
+            let result = Material::lighting(
+                &m,
+                &position,
+                &light,
+                &eyev,
+                &normalv,
+                &visible(&light, &position, intensity),
+            );
+            assert_eq!(result, Color::new(expected, expected, expected));
+        }
+    }
+
+    #[test]
+    fn area_lighting_averages_samples() {
+        let mut light = AreaLight::rect(
+            Vec4::point(-0.5, -0.5, -5.0),
+            Vec4::vector(1.0, 0.0, 0.0),
+            2,
+            Vec4::vector(0.0, 1.0, 0.0),
+            2,
+            Color::white(),
+        );
+        light.jitter = false;
+        let mut m = Material::default();
+        m.ambient = 0.1;
+        m.diffuse = 0.9;
+        m.specular = 0.0;
+        let eyev = Vec4::vector(0.0, 0.0, -1.0);
+
+        let point = Vec4::point(0.0, 0.0, -1.0);
+        let normalv = Vec4::vector(0.0, 0.0, -1.0);
+        let c = Material::lighting(
+            &m,
+            &point,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &point, Color::white()),
+        );
+        assert_eq!(c, Color::new(0.99651, 0.99651, 0.99651));
+
+        let c = Material::lighting(
+            &m,
+            &point,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &point, Color::black()),
+        );
+        assert_eq!(c, Color::new(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn each_light_sample_is_weighted_by_its_own_transmittance() {
+        // one sample to the left of the point and one to the right
+        let mut light = AreaLight::rect(
+            Vec4::point(-5.0, 0.0, -5.0),
+            Vec4::vector(10.0, 0.0, 0.0),
+            2,
+            Vec4::vector(0.0, 0.001, 0.0),
+            1,
+            Color::white(),
+        );
+        light.jitter = false;
+        let m = Material::default();
+        let point = Vec4::point(0.0, 0.0, 0.0);
+        let eyev = Vec4::vector(0.0, 0.0, -1.0);
+        let normalv = Vec4::vector(-1.0, 0.0, -1.0).norm();
+        let shade = |left: Color, right: Color| {
+            let mut samples = visible(&light, &point, Color::white());
+            samples[0].1 = left;
+            samples[1].1 = right;
+            Material::lighting(&m, &point, &light, &eyev, &normalv, &samples)
+        };
+        let ambient = Color::new(0.1, 0.1, 0.1);
+        let left = shade(Color::white(), Color::black()) - ambient;
+        let right = shade(Color::black(), Color::white()) - ambient;
+        assert!(left.luminance() > right.luminance());
+        assert_eq!(
+            left + right + ambient,
+            shade(Color::white(), Color::white())
+        );
+    }
+
+    #[test]
+    fn lighting_with_directional_and_spot_lights() {
+        let m = Material::default();
+        let position = Vec4::point(0.0, 0.0, 0.0);
+        let eyev = Vec4::vector(0.0, 0.0, -1.0);
+        let normalv = Vec4::vector(0.0, 0.0, -1.0);
+
+        let sun = DirectionalLight::new(Vec4::vector(0.0, 0.0, 1.0), Color::white());
+        let result = Material::lighting(
+            &m,
+            &position,
+            &sun,
+            &eyev,
+            &normalv,
+            &visible(&sun, &position, Color::white()),
+        );
+        assert_eq!(result, Color::new(1.9, 1.9, 1.9));
+
+        let spot = SpotLight::new(
+            Vec4::point(0.0, 0.0, -10.0),
+            Vec4::vector(0.0, 0.0, 1.0),
+            0.1,
+            0.2,
+            Color::white(),
+        );
+        let result = Material::lighting(
+            &m,
+            &position,
+            &spot,
+            &eyev,
+            &normalv,
+            &visible(&spot, &position, Color::white()),
+        );
+        assert_eq!(result, Color::new(1.9, 1.9, 1.9));
+
+        // outside of the outer cone only the ambient term is left
+        let point = Vec4::point(5.0, 0.0, 0.0);
+        let result = Material::lighting(
+            &m,
+            &point,
+            &spot,
+            &eyev,
+            &normalv,
+            &visible(&spot, &point, Color::white()),
+        );
+        assert_eq!(result, Color::new(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn ggx_distribution_is_normalized() {
+        // the projected microfacet area adds up to one
+        let steps = 10_000;
+        for roughness in [0.3, 0.7, 1.0] {
+            let dtheta = PI / 2.0 / steps as f64;
+            let total: f64 = (0..steps)
+                .map(|i| {
+                    let theta = (i as f64 + 0.5) * dtheta;
+                    let cos = theta.cos();
+                    ggx_distribution(cos, roughness) * cos * theta.sin() * 2.0 * PI * dtheta
+                })
+                .sum();
+            assert!((total - 1.0).abs() < 1e-3, "roughness {roughness}: {total}");
+        }
+    }
+
+    #[test]
+    fn fresnel_rises_to_white_at_grazing_angles() {
+        let f0 = Color::new(1.0, 0.8, 0.3);
+        assert_eq!(fresnel_schlick(f0, 1.0), f0);
+        assert_eq!(fresnel_schlick(f0, 0.0), Color::white());
+        assert_eq!(fresnel_schlick(f0, 0.5), Color::new(1.0, 0.80625, 0.321875));
+    }
+
+    #[test]
+    fn pbr_lighting_head_on() {
+        let position = Vec4::point(0.0, 0.0, 0.0);
+        let eyev = Vec4::vector(0.0, 0.0, -1.0);
+        let normalv = Vec4::vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(Vec4::point(0.0, 0.0, -10.0), Color::white());
+        let gold = Color::new(1.0, 0.8, 0.3);
+        // with roughness 1 head-on, D = 1/π and G = 1
+        let cases = [
+            (
+                Material::pbr(Color::white(), 0.0, 1.0),
+                Color::new(1.07, 1.07, 1.07),
+            ),
+            (Material::pbr(gold, 1.0, 1.0), gold * 0.35),
+            (
+                Material::pbr(gold, 0.5, 1.0),
+                Color::new(0.47, 0.417, 0.197),
+            ),
+        ];
+        for (m, expected) in cases {
+            let result = Material::lighting(
+                &m,
+                &position,
+                &light,
+                &eyev,
+                &normalv,
+                &visible(&light, &position, Color::white()),
+            );
+            assert_eq!(result, expected);
+        }
+
+        // behind the surface only the ambient term is left
+        let light = PointLight::new(Vec4::point(0.0, 0.0, 10.0), Color::white());
+        let m = Material::pbr(gold, 1.0, 0.2);
+        let result = Material::lighting(
+            &m,
+            &position,
+            &light,
+            &eyev,
+            &normalv,
+            &visible(&light, &position, Color::white()),
+        );
+        assert_eq!(result, gold * 0.1);
+    }
+
+    #[test]
+    fn pbr_reflectance_fades_with_roughness() {
+        let point = Vec4::point(0.0, 0.0, 0.0);
+        let normalv = Vec4::vector(0.0, 1.0, 0.0);
+        let grazing = Vec4::vector(0.0, 0.01, -1.0).norm();
+        let head_on = Vec4::vector(0.0, 1.0, 0.0);
+        for (roughness, min_grazing) in [(0.0, 0.9), (0.5, 0.45), (1.0, 0.0)] {
+            let m = Material::pbr(Color::white(), 0.0, roughness);
+            assert_eq!(m.reflective, 0.0);
+            let r = m.reflectance(&point, &head_on, &normalv);
+            assert_eq!(r, Color::new(0.04, 0.04, 0.04));
+            let r = m.reflectance(&point, &grazing, &normalv);
+            assert!(r.r > min_grazing && r.r <= (1.0 - roughness).max(0.04) + EPSILON);
+        }
+    }
+
+    #[test]
+    fn smooth_metal_highlight_is_tight() {
+        let position = Vec4::point(0.0, 0.0, 0.0);
+        let normalv = Vec4::vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(Vec4::point(0.0, 10.0, -10.0), Color::white());
+        let mut m = Material::pbr(Color::white(), 1.0, 0.1);
+        m.ambient = 0.0;
+        let lit = |eyev: Vec4| {
+            Material::lighting(
+                &m,
+                &position,
+                &light,
+                &eyev,
+                &normalv,
+                &visible(&light, &position, Color::white()),
+            )
+            .luminance()
+        };
+        let mirror = lit(Vec4::vector(0.0, -SQRT_2 / 2.0, -SQRT_2 / 2.0));
+        let head_on = lit(Vec4::vector(0.0, 0.0, -1.0));
+        assert!(mirror > 10.0);
+        assert!(head_on < 0.01);
+    }
+
+    #[test]
+    fn ggx_samples_gather_around_the_normal() {
+        let mut rng = rand::rng();
+        let normal = Vec4::vector(1.0, 0.0, 0.0);
+        for (roughness, min_mean_cos) in [(0.05, 0.999), (0.5, 0.8)] {
+            let cosines: Vec<f64> = (0..1000)
+                .map(|_| sample_ggx(&normal, roughness, &mut rng).dot(&normal))
+                .collect();
+            assert!(cosines.iter().all(|&cos| cos > 0.0));
+            assert!(cosines.iter().sum::<f64>() / 1000.0 > min_mean_cos);
+        }
+    }
+
+    #[test]
+    fn dispersion_bends_blue_more_than_red() {
+        let cases = [
+            (Dispersion::BK7, 587.6, 1.5168),
+            (Dispersion::DIAMOND, 587.6, 2.4175),
+            (Dispersion::Cauchy { a: 1.5, b: 0.01 }, 500.0, 1.54),
+        ];
+        for (dispersion, wavelength, expected) in cases {
+            assert!((dispersion.index(wavelength) - expected).abs() < 1e-3);
+            assert!(dispersion.index(450.0) > dispersion.index(650.0));
+        }
+
+        let mut m = Material::glas();
+        m.dispersion = Some(Dispersion::BK7);
+        assert_eq!(m.refractive_index_at(None), 1.5);
+        assert_eq!(
+            m.refractive_index_at(Some(500.0)),
+            Dispersion::BK7.index(500.0)
+        );
+    }
+
     #[test]
     fn pattern_test() {
         let mut m = Material::default();
@@ -192,24 +756,23 @@ pub mod tests {
         let eyev = Vec4::vector(0.0, 0.0, -1.0);
         let normalv = Vec4::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Vec4::point(0.0, 0.0, -10.0), Color::white());
-        let s = Box::new(Sphere::new());
+        let p1 = Vec4::point(0.9, 0.0, 0.0);
         let c1 = Material::lighting(
             &m,
-            s.as_ref(),
+            &p1,
             &light,
-            &Vec4::point(0.9, 0.0, 0.0),
             &eyev,
             &normalv,
-            false,
+            &visible(&light, &p1, Color::white()),
         );
+        let p2 = Vec4::point(1.1, 0.0, 0.0);
         let c2 = Material::lighting(
             &m,
-            s.as_ref(),
+            &p2,
             &light,
-            &Vec4::point(1.1, 0.0, 0.0),
             &eyev,
             &normalv,
-            false,
+            &visible(&light, &p2, Color::white()),
         );
         assert_eq!(c1, Color::white());
         assert_eq!(c2, Color::black());
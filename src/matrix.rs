@@ -2,38 +2,29 @@ use crate::math::ApproxEq;
 use crate::vec4::Vec4;
 use num_traits::ToPrimitive;
 use std::fmt;
-use std::ops::{Index, IndexMut, Mul};
+use std::ops::{Add, Index, IndexMut, Mul};
 
 pub type SqMatrix<const N: usize> = Matrix<N, N>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 pub struct Matrix<const ROWS: usize, const COLS: usize> {
-    data: Vec<f64>,
+    data: [[f64; COLS]; ROWS],
 }
 
 impl<const ROWS: usize, const COLS: usize> Matrix<ROWS, COLS> {
     pub fn new() -> Self {
-        let rows = ROWS;
-        let cols = COLS;
-        let total = rows * cols;
         Matrix {
-            data: vec![0.0; total],
+            data: [[0.0; COLS]; ROWS],
         }
     }
     pub fn from_array(array: [[f64; COLS]; ROWS]) -> Self {
-        let mut mat: Matrix<ROWS, COLS> = Matrix::new();
-        for row in 0..ROWS {
-            for col in 0..COLS {
-                mat[(row, col)] = array[row][col];
-            }
-        }
-        mat
+        Matrix { data: array }
     }
     pub fn transpose(&self) -> Matrix<COLS, ROWS> {
         let mut transposed: Matrix<COLS, ROWS> = Matrix::new();
         for i in 0..ROWS {
             for j in 0..COLS {
-                transposed[(j, i)] = self[(i, j)];
+                transposed.data[j][i] = self.data[i][j];
             }
         }
         transposed
@@ -44,7 +35,7 @@ impl<const N: usize> Matrix<N, N> {
     pub fn eye() -> Self {
         let mut mat: Matrix<N, N> = Matrix::new();
         for i in 0..N {
-            mat[(i, i)] = 1.0;
+            mat.data[i][i] = 1.0;
         }
         mat
     }
@@ -72,7 +63,7 @@ impl Matrix<4, 4> {
     }
     fn cofactor(&self, row: usize, col: usize) -> f64 {
         let minor = self.submatrix(row, col).det();
-        if (row + col) % 2 == 0 {
+        if (row + col).is_multiple_of(2) {
             return minor;
         }
         -minor
@@ -84,21 +75,108 @@ impl Matrix<4, 4> {
         }
         det
     }
+    /// Whether the last row is `0 0 0 1`, true for every combination of
+    /// translations, rotations, scalings and shearings.
+    pub fn is_affine(&self) -> bool {
+        self.data[3] == [0.0, 0.0, 0.0, 1.0]
+    }
     pub fn inverse(&self) -> Matrix<4, 4> {
-        let det = self.det();
+        if self.is_affine() {
+            self.affine_inverse()
+        } else {
+            self.general_inverse()
+        }
+    }
+
+    // inverts the upper 3x3 block and moves the translation through it
+    fn affine_inverse(&self) -> Matrix<4, 4> {
+        let a = &self.data;
+        let c00 = a[1][1] * a[2][2] - a[1][2] * a[2][1];
+        let c01 = a[1][2] * a[2][0] - a[1][0] * a[2][2];
+        let c02 = a[1][0] * a[2][1] - a[1][1] * a[2][0];
+        let det = a[0][0] * c00 + a[0][1] * c01 + a[0][2] * c02;
         if det.approx_eq(&0.0) {
             panic!("Tried to invert non invertable Matrix!");
         }
+        let inv_det = 1.0 / det;
+
+        let r = [
+            [
+                c00 * inv_det,
+                (a[0][2] * a[2][1] - a[0][1] * a[2][2]) * inv_det,
+                (a[0][1] * a[1][2] - a[0][2] * a[1][1]) * inv_det,
+            ],
+            [
+                c01 * inv_det,
+                (a[0][0] * a[2][2] - a[0][2] * a[2][0]) * inv_det,
+                (a[0][2] * a[1][0] - a[0][0] * a[1][2]) * inv_det,
+            ],
+            [
+                c02 * inv_det,
+                (a[0][1] * a[2][0] - a[0][0] * a[2][1]) * inv_det,
+                (a[0][0] * a[1][1] - a[0][1] * a[1][0]) * inv_det,
+            ],
+        ];
+        let (tx, ty, tz) = (a[0][3], a[1][3], a[2][3]);
 
-        let mut inverse: Matrix<4, 4> = Matrix::new();
-        for row in 0..4 {
-            for col in 0..4 {
-                let c = self.cofactor(row, col);
-                inverse[(col, row)] = c / det;
-            }
+        let mut inverse = Matrix::<4, 4>::eye();
+        for (row, r) in inverse.data.iter_mut().zip(r) {
+            row[..3].copy_from_slice(&r);
+            row[3] = -(r[0] * tx + r[1] * ty + r[2] * tz);
         }
         inverse
     }
+
+    // closed form inverse from the 2x2 sub determinants of the upper and lower half
+    fn general_inverse(&self) -> Matrix<4, 4> {
+        let a = &self.data;
+        let s0 = a[0][0] * a[1][1] - a[1][0] * a[0][1];
+        let s1 = a[0][0] * a[1][2] - a[1][0] * a[0][2];
+        let s2 = a[0][0] * a[1][3] - a[1][0] * a[0][3];
+        let s3 = a[0][1] * a[1][2] - a[1][1] * a[0][2];
+        let s4 = a[0][1] * a[1][3] - a[1][1] * a[0][3];
+        let s5 = a[0][2] * a[1][3] - a[1][2] * a[0][3];
+
+        let c5 = a[2][2] * a[3][3] - a[3][2] * a[2][3];
+        let c4 = a[2][1] * a[3][3] - a[3][1] * a[2][3];
+        let c3 = a[2][1] * a[3][2] - a[3][1] * a[2][2];
+        let c2 = a[2][0] * a[3][3] - a[3][0] * a[2][3];
+        let c1 = a[2][0] * a[3][2] - a[3][0] * a[2][2];
+        let c0 = a[2][0] * a[3][1] - a[3][0] * a[2][1];
+
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if det.approx_eq(&0.0) {
+            panic!("Tried to invert non invertable Matrix!");
+        }
+        let inv_det = 1.0 / det;
+
+        Matrix::from_array([
+            [
+                (a[1][1] * c5 - a[1][2] * c4 + a[1][3] * c3) * inv_det,
+                (-a[0][1] * c5 + a[0][2] * c4 - a[0][3] * c3) * inv_det,
+                (a[3][1] * s5 - a[3][2] * s4 + a[3][3] * s3) * inv_det,
+                (-a[2][1] * s5 + a[2][2] * s4 - a[2][3] * s3) * inv_det,
+            ],
+            [
+                (-a[1][0] * c5 + a[1][2] * c2 - a[1][3] * c1) * inv_det,
+                (a[0][0] * c5 - a[0][2] * c2 + a[0][3] * c1) * inv_det,
+                (-a[3][0] * s5 + a[3][2] * s2 - a[3][3] * s1) * inv_det,
+                (a[2][0] * s5 - a[2][2] * s2 + a[2][3] * s1) * inv_det,
+            ],
+            [
+                (a[1][0] * c4 - a[1][1] * c2 + a[1][3] * c0) * inv_det,
+                (-a[0][0] * c4 + a[0][1] * c2 - a[0][3] * c0) * inv_det,
+                (a[3][0] * s4 - a[3][1] * s2 + a[3][3] * s0) * inv_det,
+                (-a[2][0] * s4 + a[2][1] * s2 - a[2][3] * s0) * inv_det,
+            ],
+            [
+                (-a[1][0] * c3 + a[1][1] * c1 - a[1][2] * c0) * inv_det,
+                (a[0][0] * c3 - a[0][1] * c1 + a[0][2] * c0) * inv_det,
+                (-a[3][0] * s3 + a[3][1] * s1 - a[3][2] * s0) * inv_det,
+                (a[2][0] * s3 - a[2][1] * s1 + a[2][2] * s0) * inv_det,
+            ],
+        ])
+    }
 }
 
 impl Matrix<3, 3> {
@@ -127,7 +205,7 @@ impl Matrix<3, 3> {
     }
 
     fn cofactor(&self, row: usize, col: usize) -> f64 {
-        if row + col % 2 == 0 {
+        if (row + col).is_multiple_of(2) {
             return self.minor(row, col);
         }
         -self.minor(row, col)
@@ -151,15 +229,13 @@ impl Matrix<2, 2> {
 impl<const ROWS: usize, const COLS: usize> Index<(usize, usize)> for Matrix<ROWS, COLS> {
     type Output = f64;
     fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
-        debug_assert!(row < ROWS && col < COLS, "Index out of bounds");
-        &self.data[row * COLS + col]
+        &self.data[row][col]
     }
 }
 
 impl<const ROWS: usize, const COLS: usize> IndexMut<(usize, usize)> for Matrix<ROWS, COLS> {
     fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Self::Output {
-        debug_assert!(row < ROWS && col < COLS, "Index out of bounds");
-        &mut self.data[row * COLS + col]
+        &mut self.data[row][col]
     }
 }
 
@@ -182,66 +258,74 @@ impl<const ROWS: usize, const COLS: usize> fmt::Display for Matrix<ROWS, COLS> {
 
 impl<const ROWS: usize, const COLS: usize> PartialEq for Matrix<ROWS, COLS> {
     fn eq(&self, other: &Self) -> bool {
-        for i in 0..self.data.len() {
-            if !self.data[i].approx_eq(&other.data[i]) {
-                return false;
-            }
+        self.data
+            .iter()
+            .flatten()
+            .zip(other.data.iter().flatten())
+            .all(|(a, b)| a.approx_eq(b))
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Add for Matrix<ROWS, COLS> {
+    type Output = Matrix<ROWS, COLS>;
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut result = self;
+        for (value, other) in result.data.iter_mut().flatten().zip(rhs.data.iter().flatten()) {
+            *value += other;
         }
-        true
+        result
     }
 }
 
-impl<'a, 'b, const N: usize> Mul<&'b Matrix<N, N>> for &'a Matrix<N, N> {
+impl<const N: usize> Mul for Matrix<N, N> {
     type Output = Matrix<N, N>;
-    fn mul(self, rhs: &'b Matrix<N, N>) -> Self::Output {
+    fn mul(self, rhs: Self) -> Self::Output {
         let mut result = Matrix::<N, N>::new();
         for row in 0..N {
             for col in 0..N {
                 let mut sum = 0.0;
                 for k in 0..N {
-                    sum += self[(row, k)] * rhs[(k, col)];
+                    sum += self.data[row][k] * rhs.data[k][col];
                 }
-                result[(row, col)] = sum;
+                result.data[row][col] = sum;
             }
         }
         result
     }
 }
 
-impl<const N: usize> Mul for Matrix<N, N> {
+impl<const N: usize> Mul<&Matrix<N, N>> for &Matrix<N, N> {
     type Output = Matrix<N, N>;
-    fn mul(self, rhs: Self) -> Self::Output {
-        &self * &rhs
+    fn mul(self, rhs: &Matrix<N, N>) -> Self::Output {
+        *self * *rhs
     }
 }
 
-impl<'a, 'b> Mul<&'b Vec4> for &'a Matrix<4, 4> {
+impl Mul<Vec4> for Matrix<4, 4> {
     type Output = Vec4;
-
-    fn mul(self, rhs: &'b Vec4) -> Vec4 {
+    fn mul(self, rhs: Vec4) -> Self::Output {
         let d = &self.data;
 
         Vec4::new(
-            d[0] * rhs.x + d[1] * rhs.y + d[2] * rhs.z + d[3] * rhs.w,
-            d[4] * rhs.x + d[5] * rhs.y + d[6] * rhs.z + d[7] * rhs.w,
-            d[8] * rhs.x + d[9] * rhs.y + d[10] * rhs.z + d[11] * rhs.w,
-            d[12] * rhs.x + d[13] * rhs.y + d[14] * rhs.z + d[15] * rhs.w,
+            d[0][0] * rhs.x + d[0][1] * rhs.y + d[0][2] * rhs.z + d[0][3] * rhs.w,
+            d[1][0] * rhs.x + d[1][1] * rhs.y + d[1][2] * rhs.z + d[1][3] * rhs.w,
+            d[2][0] * rhs.x + d[2][1] * rhs.y + d[2][2] * rhs.z + d[2][3] * rhs.w,
+            d[3][0] * rhs.x + d[3][1] * rhs.y + d[3][2] * rhs.z + d[3][3] * rhs.w,
         )
     }
 }
 
-impl<'b> Mul<&'b Vec4> for Matrix<4, 4> {
+impl Mul<&Vec4> for &Matrix<4, 4> {
     type Output = Vec4;
-
-    fn mul(self, rhs: &'b Vec4) -> Self::Output {
-        (&self) * rhs
+    fn mul(self, rhs: &Vec4) -> Vec4 {
+        *self * *rhs
     }
 }
 
-impl Mul<Vec4> for Matrix<4, 4> {
+impl Mul<&Vec4> for Matrix<4, 4> {
     type Output = Vec4;
-    fn mul(self, rhs: Vec4) -> Self::Output {
-        &self * &rhs
+    fn mul(self, rhs: &Vec4) -> Self::Output {
+        self * *rhs
     }
 }
 
@@ -252,9 +336,9 @@ where
     type Output = Matrix<ROWS, COLS>;
     fn mul(self, rhs: I) -> Self::Output {
         let scalar = rhs.to_f64().expect("Failed to convert to f64");
-        let mut result = Matrix::<ROWS, COLS>::new();
-        for i in 0..self.data.len() {
-            result.data[i] = self.data[i] * scalar;
+        let mut result = self;
+        for value in result.data.iter_mut().flatten() {
+            *value *= scalar;
         }
         result
     }
@@ -427,7 +511,7 @@ pub mod tests {
         ];
         let mat_a = Matrix::from_array(mat_values);
         let mat_eye: SqMatrix<4> = Matrix::eye();
-        let mat_b = mat_a.clone();
+        let mat_b = mat_a;
         assert_eq!((mat_a * mat_eye), mat_b);
     }
 
@@ -525,4 +609,37 @@ pub mod tests {
         assert!(eye == eye.inverse());
         assert!(&b * &b.inverse() == eye);
     }
+
+    #[test]
+    fn cofactor_sign_off_first_row() {
+        let val = [[1.0, 2.0, 6.0], [-5.0, 8.0, -4.0], [2.0, 6.0, 4.0]];
+        let mat = Matrix::from_array(val);
+        assert_eq!(mat.cofactor(2, 0), mat.minor(2, 0));
+        assert_eq!(mat.cofactor(2, 1), -mat.minor(2, 1));
+        assert_eq!(mat.cofactor(1, 1), mat.minor(1, 1));
+    }
+
+    #[test]
+    fn affine_inverse_matches_general_inverse() {
+        let m = Matrix::translation(1.0, -2.0, 3.0)
+            * Matrix::rotation_y(0.7)
+            * Matrix::rotation_x(-1.2)
+            * Matrix::scaling(2.0, 0.5, 3.0)
+            * Matrix::shearing(0.3, 0.0, 0.1, 0.0, 0.0, 0.4);
+        assert!(m.is_affine());
+        assert_eq!(m.affine_inverse(), m.general_inverse());
+        assert_eq!(m * m.inverse(), Matrix::eye());
+
+        let mut projective = m;
+        projective[(3, 2)] = 0.5;
+        assert!(!projective.is_affine());
+        assert_eq!(projective * projective.inverse(), Matrix::eye());
+    }
+
+    #[test]
+    fn matrices_are_copy() {
+        let a = Matrix::translation(1.0, 2.0, 3.0);
+        let b = a;
+        assert_eq!(a * b, Matrix::translation(2.0, 4.0, 6.0));
+    }
 }
@@ -97,11 +97,13 @@ impl Parser {
 
         let mut top_group = Group::new();
 
-        let default_group = self.groups.remove("default").unwrap();
-        for (_name, group) in &mut self.groups.drain() {
+        let mut default_group = self.groups.remove("default").unwrap();
+        for (_name, mut group) in &mut self.groups.drain() {
+            group.update_bounds();
             top_group.add_child_without_bounds(Arc::new(group));
         }
-        if default_group.children.len() > 0 {
+        if !default_group.children().is_empty() {
+            default_group.update_bounds();
             top_group.add_child_without_bounds(Arc::new(default_group));
         }
         top_group.update_bounds();
@@ -27,7 +27,7 @@ impl Pattern for Gradient {
     }
 
     fn set_transformation(&mut self, matrix: Matrix<4, 4>) {
-        self.transform = matrix.clone();
+        self.transform = matrix;
         self.inverse = matrix.inverse();
     }
 
@@ -14,8 +14,12 @@ pub trait Pattern: Debug + Sync + Send {
     fn transform(&self) -> &Matrix<4, 4>;
     fn set_transformation(&mut self, matrix: Matrix<4, 4>);
     fn pattern_at(&self, object: &dyn Shape, world_point: &Vec4) -> Color {
-        let object_point = object.transform().inverse() * world_point;
-        let pattern_point = self.transform().inverse() * object_point;
+        let object_point = object.inverse() * world_point;
+        self.pattern_at_object(&object_point)
+    }
+    /// Color at a point that is already in the object space of the shape.
+    fn pattern_at_object(&self, object_point: &Vec4) -> Color {
+        let pattern_point = self.inverse() * object_point;
         self.color_at(&pattern_point)
     }
     fn inverse(&self) -> &Matrix<4, 4>;
@@ -44,7 +48,7 @@ impl Pattern for TestPattern {
         self.color_at(world_point)
     }
     fn set_transformation(&mut self, matrix: Matrix<4, 4>) {
-        self.transform = matrix.clone();
+        self.transform = matrix;
         self.inverse = matrix.inverse();
     }
     fn transform(&self) -> &Matrix<4, 4> {
@@ -30,7 +30,7 @@ impl Pattern for StripePattern {
         }
     }
     fn set_transformation(&mut self, matrix: Matrix<4, 4>) {
-        self.transform = matrix.clone();
+        self.transform = matrix;
         self.inverse = matrix.inverse();
     }
     fn transform(&self) -> &Matrix<4, 4> {
@@ -0,0 +1,178 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Snapshot of a running render. A pixel counts as done once per pass, so
+/// `total_pixels` is the image size times the number of passes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub pixels_done: u64,
+    pub total_pixels: u64,
+    /// Camera rays traced so far, secondary rays are not counted.
+    pub rays: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 1.0;
+        }
+        self.pixels_done as f64 / self.total_pixels as f64
+    }
+    /// Remaining time extrapolated from the speed so far, `None` before the
+    /// first pixel is done.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.pixels_done == 0 {
+            return None;
+        }
+        let remaining = (self.total_pixels - self.pixels_done) as f64 / self.pixels_done as f64;
+        Some(self.elapsed.mul_f64(remaining))
+    }
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.rays as f64 / seconds
+    }
+}
+
+/// Receives progress updates from `Camera::render`. `update` is called from
+/// the worker threads after every finished tile.
+pub trait ProgressObserver: Send + Sync {
+    fn start(&self, _total_pixels: u64) {}
+    fn update(&self, progress: &Progress);
+    fn finish(&self, _progress: &Progress) {}
+    /// Problems that don't stop the render, like a checkpoint that can't be
+    /// read or written.
+    fn warning(&self, _message: &str) {}
+}
+
+impl<T: ProgressObserver + ?Sized> ProgressObserver for Arc<T> {
+    fn start(&self, total_pixels: u64) {
+        (**self).start(total_pixels)
+    }
+    fn update(&self, progress: &Progress) {
+        (**self).update(progress)
+    }
+    fn finish(&self, progress: &Progress) {
+        (**self).finish(progress)
+    }
+    fn warning(&self, message: &str) {
+        (**self).warning(message)
+    }
+}
+
+/// Reports nothing, for tests and CI.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Silent;
+
+impl ProgressObserver for Silent {
+    fn update(&self, _progress: &Progress) {}
+}
+
+/// Terminal progress bar.
+#[derive(Debug)]
+pub struct ProgressBarObserver {
+    bar: ProgressBar,
+}
+
+impl ProgressBarObserver {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(0);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] [{wide_bar}] {pos}/{len} ({percent}%) {msg}",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+        );
+        ProgressBarObserver { bar }
+    }
+}
+
+impl Default for ProgressBarObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressObserver for ProgressBarObserver {
+    fn start(&self, total_pixels: u64) {
+        self.bar.reset();
+        self.bar.set_length(total_pixels);
+    }
+    fn update(&self, progress: &Progress) {
+        self.bar.set_position(progress.pixels_done);
+        let eta = progress.eta().map_or(0, |eta| eta.as_secs());
+        self.bar.set_message(format!(
+            "eta {}s, {:.0} rays/s",
+            eta,
+            progress.rays_per_second()
+        ));
+    }
+    fn finish(&self, progress: &Progress) {
+        self.update(progress);
+        self.bar.finish();
+    }
+    fn warning(&self, message: &str) {
+        self.bar.println(message);
+    }
+}
+
+/// Stops a render from another thread. Tiles that already started are
+/// finished, the rest are skipped and the render returns what it has.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_and_rays_per_second() {
+        let progress = Progress {
+            pixels_done: 25,
+            total_pixels: 100,
+            rays: 400,
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(6)));
+        assert_eq!(progress.rays_per_second(), 200.0);
+
+        let start = Progress {
+            pixels_done: 0,
+            elapsed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!(start.eta(), None);
+        assert_eq!(start.rays_per_second(), 0.0);
+    }
+
+    #[test]
+    fn cancellation_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let other = token.clone();
+        assert!(!other.is_cancelled());
+        token.cancel();
+        assert!(other.is_cancelled());
+    }
+}
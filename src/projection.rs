@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::vec4::Vec4;
+
+/// Maps a position on the canvas to a ray in camera space, where the camera
+/// sits at the origin looking down -z with +y up. `x` and `y` are measured in
+/// pixels from the top left corner and `lens` is a point on the aperture.
+/// Positions the projection doesn't cover, like the corners of a fisheye
+/// image, give `None`.
+pub trait Projection: Send + Sync {
+    fn ray(&self, camera: &Camera, x: f64, y: f64, lens: (f64, f64)) -> Option<Ray>;
+}
+
+/// Pinhole or thin lens projection onto a canvas one unit in front of the
+/// camera, sized by the camera's field of view.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Perspective;
+
+impl Projection for Perspective {
+    fn ray(&self, camera: &Camera, x: f64, y: f64, lens: (f64, f64)) -> Option<Ray> {
+        let canvas_x = camera.half_width - x * camera.pixel_size;
+        let canvas_y = camera.half_height - y * camera.pixel_size;
+        // every ray through this canvas point meets on the focal plane
+        let d = camera.focal_distance;
+        let focus = Vec4::point(canvas_x * d, canvas_y * d, -d);
+        let origin = Vec4::point(lens.0, lens.1, 0.0);
+        Some(Ray::from_vec4(origin, (focus - origin).norm()))
+    }
+}
+
+/// Parallel rays along -z from a view `width` units wide, the height follows
+/// from the aspect ratio of the canvas.
+#[derive(Debug, Clone, Copy)]
+pub struct Orthographic {
+    pub width: f64,
+}
+
+impl Orthographic {
+    pub fn new(width: f64) -> Self {
+        Orthographic { width }
+    }
+}
+
+impl Projection for Orthographic {
+    fn ray(&self, camera: &Camera, x: f64, y: f64, _lens: (f64, f64)) -> Option<Ray> {
+        let pixel_size = self.width / camera.hsize as f64;
+        let half_height = pixel_size * camera.vsize as f64 / 2.0;
+        Some(Ray::new(
+            self.width / 2.0 - x * pixel_size,
+            half_height - y * pixel_size,
+            0.0,
+            0.0,
+            0.0,
+            -1.0,
+        ))
+    }
+}
+
+/// Full 360° by 180° panorama, longitude runs along x and latitude along y
+/// with the view direction in the center of the image.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+    fn ray(&self, camera: &Camera, x: f64, y: f64, _lens: (f64, f64)) -> Option<Ray> {
+        let longitude = (x / camera.hsize as f64 - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - y / camera.vsize as f64) * PI;
+        let direction = Vec4::vector(
+            -latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        );
+        Some(Ray::from_vec4(Vec4::point(0.0, 0.0, 0.0), direction))
+    }
+}
+
+/// Equidistant fisheye covering `fov` radians across the largest circle that
+/// fits on the canvas, the angle to the view direction grows linearly with the
+/// distance to the center.
+#[derive(Debug, Clone, Copy)]
+pub struct Fisheye {
+    pub fov: f64,
+}
+
+impl Fisheye {
+    pub fn new(fov: f64) -> Self {
+        Fisheye { fov }
+    }
+}
+
+impl Projection for Fisheye {
+    fn ray(&self, camera: &Camera, x: f64, y: f64, _lens: (f64, f64)) -> Option<Ray> {
+        let radius = camera.hsize.min(camera.vsize) as f64 / 2.0;
+        let dx = (camera.hsize as f64 / 2.0 - x) / radius;
+        let dy = (camera.vsize as f64 / 2.0 - y) / radius;
+        let r = dx.hypot(dy);
+        if r > 1.0 {
+            return None;
+        }
+        let theta = r * self.fov / 2.0;
+        let phi = dy.atan2(dx);
+        let direction = Vec4::vector(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        );
+        Some(Ray::from_vec4(Vec4::point(0.0, 0.0, 0.0), direction))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let mut c = Camera::new(200, 100, FRAC_PI_2, 0, 1);
+        c.set_projection(Orthographic::new(10.0));
+        let cases = [
+            ((100, 50), Vec4::point(-0.025, -0.025, 0.0)),
+            ((0, 0), Vec4::point(4.975, 2.475, 0.0)),
+            ((199, 99), Vec4::point(-4.975, -2.475, 0.0)),
+        ];
+        for ((px, py), origin) in cases {
+            let r = c.ray_for_pixel(px, py).unwrap();
+            assert_eq!(r.origin, origin);
+            assert_eq!(r.direction, Vec4::vector(0.0, 0.0, -1.0));
+        }
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let c = Camera::new(360, 180, FRAC_PI_2, 0, 1);
+        let p = Equirectangular;
+        let cases = [
+            ((180.0, 90.0), Vec4::vector(0.0, 0.0, -1.0)),
+            ((90.0, 90.0), Vec4::vector(1.0, 0.0, 0.0)),
+            ((270.0, 90.0), Vec4::vector(-1.0, 0.0, 0.0)),
+            ((0.0, 90.0), Vec4::vector(0.0, 0.0, 1.0)),
+            ((180.0, 0.0), Vec4::vector(0.0, 1.0, 0.0)),
+        ];
+        for ((x, y), direction) in cases {
+            let r = p.ray(&c, x, y, (0.0, 0.0)).unwrap();
+            assert_eq!(r.origin, Vec4::point(0.0, 0.0, 0.0));
+            assert_eq!(r.direction, direction);
+        }
+    }
+
+    #[test]
+    fn fisheye_maps_radius_to_angle() {
+        let c = Camera::new(200, 100, FRAC_PI_2, 0, 1);
+        let p = Fisheye::new(PI);
+        let cases = [
+            ((100.0, 50.0), Vec4::vector(0.0, 0.0, -1.0)),
+            ((50.0, 50.0), Vec4::vector(1.0, 0.0, 0.0)),
+            (
+                (100.0, 25.0),
+                Vec4::vector(0.0, 0.5f64.sqrt(), -(0.5f64.sqrt())),
+            ),
+        ];
+        for ((x, y), direction) in cases {
+            assert_eq!(p.ray(&c, x, y, (0.0, 0.0)).unwrap().direction, direction);
+        }
+        assert!(p.ray(&c, 0.0, 0.0, (0.0, 0.0)).is_none());
+    }
+}
@@ -6,6 +6,12 @@ use crate::{matrix::SqMatrix, vec4::Vec4};
 pub struct Ray {
     pub origin: Vec4,
     pub direction: Vec4,
+    /// Point in time within the camera shutter, used by moving shapes.
+    pub time: f64,
+    /// Wavelength in nanometres for spectral rendering, `None` for plain RGB.
+    pub wavelength: Option<f64>,
+    /// Reflections and refractions on the way from the camera, 0 for camera rays.
+    pub bounces: usize,
 }
 
 impl PartialEq for Ray {
@@ -19,10 +25,31 @@ impl Ray {
         Ray {
             origin: Vec4::point(x, y, z),
             direction: Vec4::vector(dx, dy, dz),
+            time: 0.0,
+            wavelength: None,
+            bounces: 0,
         }
     }
     pub fn from_vec4(origin: Vec4, direction: Vec4) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+            wavelength: None,
+            bounces: 0,
+        }
+    }
+    pub fn at_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+    pub fn with_bounces(mut self, bounces: usize) -> Self {
+        self.bounces = bounces;
+        self
     }
     pub fn position(&self, t: f64) -> Vec4 {
         self.origin + self.direction * t
@@ -33,12 +60,18 @@ impl Ray {
         Ray {
             origin: new_origin,
             direction: new_dir,
+            time: self.time,
+            wavelength: self.wavelength,
+            bounces: self.bounces,
         }
     }
     pub fn neg(&self) -> Ray {
         Self {
-            origin: self.origin.clone(),
+            origin: self.origin,
             direction: self.direction.neg(),
+            time: self.time,
+            wavelength: self.wavelength,
+            bounces: self.bounces,
         }
     }
     pub fn neg_mut(&mut self) {
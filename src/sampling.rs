@@ -0,0 +1,281 @@
+use rand::{Rng, RngCore};
+
+/// Generates the sub-pixel offsets used for anti-aliasing. Every offset lies
+/// in `[0, 1)` on both axes, relative to the top left corner of the pixel.
+pub trait Sampler: Send + Sync {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)>;
+}
+
+/// Jittered grid that works for any sample count. The pixel is cut into
+/// `round(sqrt(n))` rows and the samples are spread as evenly as possible
+/// over them, each sample getting its own cell within its row.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stratified;
+
+impl Sampler for Stratified {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+        let rows = ((n as f64).sqrt().round() as usize).max(1);
+        let mut samples = Vec::with_capacity(n);
+        for row in 0..rows {
+            // the first n % rows rows take one extra sample
+            let cols = n / rows + usize::from(row < n % rows);
+            for col in 0..cols {
+                let x = (col as f64 + rng.random::<f64>()) / cols as f64;
+                let y = (row as f64 + rng.random::<f64>()) / rows as f64;
+                samples.push((x, y));
+            }
+        }
+        samples
+    }
+}
+
+/// Independent uniform samples over the whole pixel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Random;
+
+impl Sampler for Random {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|_| (rng.random::<f64>(), rng.random::<f64>()))
+            .collect()
+    }
+}
+
+/// Halton sequence in bases 2 and 3. Each pixel gets a random toroidal shift
+/// so neighbouring pixels don't share the exact same pattern.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Halton;
+
+impl Halton {
+    pub fn radical_inverse(base: usize, mut i: usize) -> f64 {
+        let inv_base = 1.0 / base as f64;
+        let mut f = inv_base;
+        let mut result = 0.0;
+        while i > 0 {
+            result += (i % base) as f64 * f;
+            i /= base;
+            f *= inv_base;
+        }
+        result
+    }
+}
+
+impl Sampler for Halton {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+        let (sx, sy) = (rng.random::<f64>(), rng.random::<f64>());
+        (0..n)
+            .map(|i| {
+                (
+                    (Halton::radical_inverse(2, i) + sx).fract(),
+                    (Halton::radical_inverse(3, i) + sy).fract(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// First two dimensions of the Sobol sequence, scrambled with a random xor
+/// per pixel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sobol;
+
+impl Sobol {
+    fn second_dimension(mut i: u32, scramble: u32) -> u32 {
+        let mut v = 1u32 << 31;
+        let mut result = scramble;
+        while i != 0 {
+            if i & 1 != 0 {
+                result ^= v;
+            }
+            i >>= 1;
+            v ^= v >> 1;
+        }
+        result
+    }
+}
+
+impl Sampler for Sobol {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+        let (sx, sy) = (rng.next_u32(), rng.next_u32());
+        let to_unit = |bits: u32| bits as f64 / (1u64 << 32) as f64;
+        (0..n as u32)
+            .map(|i| {
+                (
+                    to_unit(i.reverse_bits() ^ sx),
+                    to_unit(Sobol::second_dimension(i, sy)),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Correlated multi-jittered sampling (Kensler 2013). The samples are
+/// stratified on a 2D grid and in both 1D projections, for any count. The
+/// permutations and the jitter are hashes of the sample index, only the
+/// pattern is drawn from the random generator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CorrelatedMultiJitter;
+
+impl CorrelatedMultiJitter {
+    // hash based permutation of 0..len, selected by pattern
+    fn permute(mut i: u32, len: u32, pattern: u32) -> u32 {
+        let mut w = len - 1;
+        w |= w >> 1;
+        w |= w >> 2;
+        w |= w >> 4;
+        w |= w >> 8;
+        w |= w >> 16;
+        loop {
+            i ^= pattern;
+            i = i.wrapping_mul(0xe170893d);
+            i ^= pattern >> 16;
+            i ^= (i & w) >> 4;
+            i ^= pattern >> 8;
+            i = i.wrapping_mul(0x0929eb3f);
+            i ^= pattern >> 23;
+            i ^= (i & w) >> 1;
+            i = i.wrapping_mul(1 | pattern >> 27);
+            i = i.wrapping_mul(0x6935fa69);
+            i ^= (i & w) >> 11;
+            i = i.wrapping_mul(0x74dcb303);
+            i ^= (i & w) >> 2;
+            i = i.wrapping_mul(0x9e501cc3);
+            i ^= (i & w) >> 2;
+            i = i.wrapping_mul(0xc860a3df);
+            i &= w;
+            i ^= i >> 5;
+            if i < len {
+                break;
+            }
+        }
+        (i + pattern) % len
+    }
+
+    // hash of `i` to [0, 1), selected by pattern
+    fn randfloat(mut i: u32, pattern: u32) -> f64 {
+        i ^= pattern;
+        i ^= i >> 17;
+        i ^= i >> 10;
+        i = i.wrapping_mul(0xb36534e5);
+        i ^= i >> 12;
+        i ^= i >> 21;
+        i = i.wrapping_mul(0x93fc4795);
+        i ^= 0xdf6e307f;
+        i ^= i >> 17;
+        i = i.wrapping_mul(1 | pattern >> 18);
+        i as f64 / 4294967808.0
+    }
+}
+
+impl Sampler for CorrelatedMultiJitter {
+    fn samples(&self, n: usize, rng: &mut dyn RngCore) -> Vec<(f64, f64)> {
+        if n == 0 {
+            return vec![];
+        }
+        let pattern = rng.next_u32();
+        let count = n as u32;
+        let m = ((n as f64).sqrt().floor() as u32).max(1);
+        let rows = count.div_ceil(m);
+        (0..count)
+            .map(|i| {
+                let s = Self::permute(i, count, pattern.wrapping_mul(0x51633e2d));
+                let sx = Self::permute(s % m, m, pattern.wrapping_mul(0x68bc21eb));
+                let sy = Self::permute(s / m, rows, pattern.wrapping_mul(0x02e5be93));
+                let jx = Self::randfloat(s, pattern.wrapping_mul(0x967a889b));
+                let jy = Self::randfloat(s, pattern.wrapping_mul(0x368cc8b7));
+                let x = (sx as f64 + (sy as f64 + jx) / rows as f64) / m as f64;
+                let y = (s as f64 + jy) / count as f64;
+                (x, y)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn samplers() -> [Box<dyn Sampler>; 5] {
+        [
+            Box::new(Stratified),
+            Box::new(Random),
+            Box::new(Halton),
+            Box::new(Sobol),
+            Box::new(CorrelatedMultiJitter),
+        ]
+    }
+
+    #[test]
+    fn any_sample_count_stays_inside_the_pixel() {
+        let mut rng = rand::rng();
+        for sampler in samplers() {
+            for n in [0, 1, 2, 5, 7, 16, 32] {
+                let samples = sampler.samples(n, &mut rng);
+                assert_eq!(samples.len(), n);
+                for (x, y) in samples {
+                    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_fills_every_row() {
+        let mut rng = rand::rng();
+        // 7 samples over 3 rows: 3, 2 and 2 samples
+        let samples = Stratified.samples(7, &mut rng);
+        let per_row = |row: f64| {
+            samples
+                .iter()
+                .filter(|(_, y)| (y * 3.0).floor() == row)
+                .count()
+        };
+        assert_eq!((per_row(0.0), per_row(1.0), per_row(2.0)), (3, 2, 2));
+    }
+
+    #[test]
+    fn halton_radical_inverse() {
+        let cases = [
+            (2, 1, 0.5),
+            (2, 3, 0.75),
+            (2, 6, 0.375),
+            (3, 1, 1.0 / 3.0),
+            (3, 5, 7.0 / 9.0),
+        ];
+        for (base, i, expected) in cases {
+            assert!((Halton::radical_inverse(base, i) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_are_stratified_in_1d() {
+        let mut rng = rand::rng();
+        let n = 16;
+        let low_discrepancy: [Box<dyn Sampler>; 2] =
+            [Box::new(Sobol), Box::new(CorrelatedMultiJitter)];
+        for sampler in low_discrepancy {
+            let samples = sampler.samples(n, &mut rng);
+            let mut xs = vec![false; n];
+            let mut ys = vec![false; n];
+            for (x, y) in samples {
+                xs[(x * n as f64) as usize] = true;
+                ys[(y * n as f64) as usize] = true;
+            }
+            assert!(xs.iter().all(|&hit| hit));
+            assert!(ys.iter().all(|&hit| hit));
+        }
+    }
+
+    #[test]
+    fn correlated_multi_jitter_is_a_function_of_the_pattern() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let samples = CorrelatedMultiJitter.samples(32, &mut rng);
+        let mut replay = StdRng::seed_from_u64(7);
+        replay.next_u32();
+        assert_eq!(rng.next_u64(), replay.next_u64());
+        let again = CorrelatedMultiJitter.samples(32, &mut StdRng::seed_from_u64(7));
+        assert_eq!(samples, again);
+    }
+}
@@ -0,0 +1,289 @@
+use super::{Shape, next_shape_id};
+use crate::bounds::Bounds;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::math::EPSILON;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::ray::Ray;
+use crate::vec4::Vec4;
+
+/// Double napped cone around the y axis, the radius at height `y` is `|y|`.
+#[derive(Debug)]
+pub struct Cone {
+    pub id: usize,
+    pub transform: SqMatrix<4>,
+    pub material: Material,
+    pub inverse: SqMatrix<4>,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub closed: bool,
+}
+
+impl Cone {
+    pub fn new() -> Cone {
+        Cone::truncated(f64::NEG_INFINITY, f64::INFINITY, false)
+    }
+    pub fn truncated(minimum: f64, maximum: f64, closed: bool) -> Cone {
+        Cone {
+            id: next_shape_id(),
+            transform: Matrix::eye(),
+            material: Material::default(),
+            inverse: Matrix::eye(),
+            minimum,
+            maximum,
+            closed,
+        }
+    }
+    pub fn with_transformation(mat: Matrix<4, 4>) -> Self {
+        let mut cone = Cone::new();
+        cone.set_transformation(mat);
+        cone
+    }
+
+    // the cap at height y has radius |y|
+    fn check_cap(ray: &Ray, t: f64, y: f64) -> bool {
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        (x.powi(2) + z.powi(2)) <= y.powi(2)
+    }
+
+    fn intersect_caps<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if !self.closed || ray.direction.y.abs() < EPSILON {
+            return;
+        }
+        let t = (self.minimum - ray.origin.y) / ray.direction.y;
+        if Cone::check_cap(ray, t, self.minimum) {
+            xs.push(Intersection::new(t, self, None, None));
+        }
+        let t = (self.maximum - ray.origin.y) / ray.direction.y;
+        if Cone::check_cap(ray, t, self.maximum) {
+            xs.push(Intersection::new(t, self, None, None));
+        }
+    }
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Cone {
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let mut xs = Vec::new();
+        let (o, d) = (ray.origin, ray.direction);
+        let a = d.x.powi(2) - d.y.powi(2) + d.z.powi(2);
+        let b = 2.0 * o.x * d.x - 2.0 * o.y * d.y + 2.0 * o.z * d.z;
+        let c = o.x.powi(2) - o.y.powi(2) + o.z.powi(2);
+
+        if a.abs() < EPSILON {
+            // ray is parallel to one of the halves, it hits the other one at most once
+            if b.abs() >= EPSILON {
+                let t = -c / (2.0 * b);
+                let y = o.y + t * d.y;
+                if self.minimum < y && y < self.maximum {
+                    xs.push(Intersection::new(t, self, None, None));
+                }
+            }
+        } else {
+            let disc = b.powi(2) - 4.0 * a * c;
+            if disc < 0.0 {
+                return xs;
+            }
+
+            let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
+            let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            for t in [t0, t1] {
+                let y = o.y + t * d.y;
+                if self.minimum < y && y < self.maximum {
+                    xs.push(Intersection::new(t, self, None, None));
+                }
+            }
+        }
+
+        self.intersect_caps(ray, &mut xs);
+        xs
+    }
+
+    fn local_normal_at(&self, local_point: Vec4, _i: &Intersection) -> Vec4 {
+        let dist = local_point.x.powi(2) + local_point.z.powi(2);
+        if dist < self.maximum.powi(2) && local_point.y >= self.maximum - EPSILON {
+            Vec4::vector(0.0, 1.0, 0.0)
+        } else if dist < self.minimum.powi(2) && local_point.y <= self.minimum + EPSILON {
+            Vec4::vector(0.0, -1.0, 0.0)
+        } else {
+            let mut y = dist.sqrt();
+            if local_point.y > 0.0 {
+                y = -y;
+            }
+            Vec4::vector(local_point.x, y, local_point.z)
+        }
+    }
+
+    fn transform(&self) -> &SqMatrix<4> {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn set_transformation(&mut self, mat: Matrix<4, 4>) {
+        self.transform = mat;
+        self.inverse = mat.inverse();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn inverse(&self) -> &Matrix<4, 4> {
+        &self.inverse
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn bounds(&self) -> Bounds {
+        let r = self.minimum.abs().max(self.maximum.abs());
+        Bounds::new(
+            Vec4::point(-r, self.minimum, -r),
+            Vec4::point(r, self.maximum, r),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::shapes::group::Group;
+
+    #[test]
+    fn ray_hits_cone() {
+        let cone = Cone::new();
+        let cases = [
+            (
+                Vec4::point(0.0, 0.0, -5.0),
+                Vec4::vector(0.0, 0.0, 1.0),
+                5.0,
+                5.0,
+            ),
+            (
+                Vec4::point(0.0, 0.0, -5.0),
+                Vec4::vector(1.0, 1.0, 1.0),
+                8.66025,
+                8.66025,
+            ),
+            (
+                Vec4::point(1.0, 1.0, -5.0),
+                Vec4::vector(-0.5, -1.0, 1.0),
+                4.55006,
+                49.44994,
+            ),
+        ];
+        for (origin, direction, t0, t1) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            let xs = cone.local_intersect(&r);
+            assert_eq!(xs.len(), 2);
+            assert!((xs[0].t - t0).abs() < EPSILON);
+            assert!((xs[1].t - t1).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn ray_parallel_to_one_half() {
+        let cone = Cone::new();
+        let r = Ray::from_vec4(
+            Vec4::point(0.0, 0.0, -1.0),
+            Vec4::vector(0.0, 1.0, 1.0).norm(),
+        );
+        let xs = cone.local_intersect(&r);
+        assert_eq!(xs.len(), 1);
+        assert!((xs[0].t - 0.35355).abs() < EPSILON);
+    }
+
+    #[test]
+    fn ray_hits_cone_caps() {
+        let cone = Cone::truncated(-0.5, 0.5, true);
+        let cases = [
+            (Vec4::point(0.0, 0.0, -5.0), Vec4::vector(0.0, 1.0, 0.0), 0),
+            (Vec4::point(0.0, 0.0, -0.25), Vec4::vector(0.0, 1.0, 1.0), 2),
+            (Vec4::point(0.0, 0.0, -0.25), Vec4::vector(0.0, 1.0, 0.0), 4),
+        ];
+        for (origin, direction, count) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            assert_eq!(cone.local_intersect(&r).len(), count);
+        }
+    }
+
+    #[test]
+    fn cone_normals() {
+        let cone = Cone::new();
+        let dummy = Cone::new();
+        let i = Intersection::new(0.0, &dummy, None, None);
+        let cases = [
+            (Vec4::point(0.0, 0.0, 0.0), Vec4::vector(0.0, 0.0, 0.0)),
+            (
+                Vec4::point(1.0, 1.0, 1.0),
+                Vec4::vector(1.0, -(2.0f64.sqrt()), 1.0),
+            ),
+            (Vec4::point(-1.0, -1.0, 0.0), Vec4::vector(-1.0, 1.0, 0.0)),
+        ];
+        for (point, normal) in cases {
+            assert_eq!(cone.local_normal_at(point, &i), normal);
+        }
+
+        let cone = Cone::truncated(-1.0, 2.0, true);
+        assert_eq!(
+            cone.local_normal_at(Vec4::point(0.5, 2.0, 0.5), &i),
+            Vec4::vector(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            cone.local_normal_at(Vec4::point(0.5, -1.0, 0.0), &i),
+            Vec4::vector(0.0, -1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn cone_bounds() {
+        assert!(!Cone::new().bounds().is_finite());
+        let cone = Cone::truncated(-5.0, 3.0, false);
+        assert_eq!(
+            cone.bounds(),
+            Bounds::new(Vec4::point(-5.0, -5.0, -5.0), Vec4::point(5.0, 3.0, 5.0))
+        );
+    }
+
+    #[test]
+    fn truncated_cone_in_group() {
+        let mut g = Group::new();
+        let mut cone = Cone::truncated(0.0, 1.0, true);
+        cone.set_transformation(Matrix::translation(5.0, 0.0, 0.0));
+        g.add_child(Arc::new(cone));
+        g.update_bounds();
+        assert_eq!(
+            g.bounds(),
+            Bounds::new(Vec4::point(4.0, 0.0, -1.0), Vec4::point(6.0, 1.0, 1.0))
+        );
+
+        let hit = Ray::new(5.0, 5.0, 0.0, 0.0, -1.0, 0.0);
+        assert_eq!(g.intersect(&hit).len(), 2);
+        let miss = Ray::new(0.0, 5.0, 0.0, 0.0, -1.0, 0.0);
+        assert_eq!(g.intersect(&miss).len(), 0);
+    }
+}
@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use super::group::Group;
+use super::{Shape, next_shape_id};
+use crate::bounds::Bounds;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::ray::Ray;
+use crate::vec4::Vec4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    /// Whether a hit on the left (`lhit`) or right operand survives, given if the
+    /// ray is currently inside the left (`inl`) and the right (`inr`) operand.
+    /// That is the case when crossing it enters or leaves the combined shape.
+    pub fn intersection_allowed(&self, lhit: bool, inl: bool, inr: bool) -> bool {
+        let (after_l, after_r) = if lhit { (!inl, inr) } else { (inl, !inr) };
+        self.contains(inl, inr) != self.contains(after_l, after_r)
+    }
+    /// Whether a point inside the left (`inl`) and the right (`inr`) operand
+    /// is inside the combined shape.
+    pub fn contains(&self, inl: bool, inr: bool) -> bool {
+        match self {
+            CsgOp::Union => inl || inr,
+            CsgOp::Intersection => inl && inr,
+            CsgOp::Difference => inl && !inr,
+        }
+    }
+}
+
+/// Combination of two shapes. Like a `Group`, the operands are placed in the
+/// object space of the `Csg` and keep their own transforms.
+#[derive(Debug)]
+pub struct Csg {
+    pub id: usize,
+    pub op: CsgOp,
+    pub left: Arc<dyn Shape + Send + Sync>,
+    pub right: Arc<dyn Shape + Send + Sync>,
+    pub transform: SqMatrix<4>,
+    pub inverse: SqMatrix<4>,
+    pub bounds: Bounds,
+    /// Replaces the material of both operands when set, the operands are
+    /// not modified so they can be shared.
+    pub material: Option<Material>,
+}
+
+impl Csg {
+    pub fn new(
+        op: CsgOp,
+        left: Arc<dyn Shape + Send + Sync>,
+        right: Arc<dyn Shape + Send + Sync>,
+    ) -> Self {
+        let mut csg = Self {
+            id: next_shape_id(),
+            op,
+            left,
+            right,
+            transform: Matrix::eye(),
+            inverse: Matrix::eye(),
+            bounds: Bounds::infinite(),
+            material: None,
+        };
+        csg.update_bounds();
+        csg
+    }
+    pub fn union(left: Arc<dyn Shape + Send + Sync>, right: Arc<dyn Shape + Send + Sync>) -> Self {
+        Csg::new(CsgOp::Union, left, right)
+    }
+    pub fn intersection(
+        left: Arc<dyn Shape + Send + Sync>,
+        right: Arc<dyn Shape + Send + Sync>,
+    ) -> Self {
+        Csg::new(CsgOp::Intersection, left, right)
+    }
+    pub fn difference(
+        left: Arc<dyn Shape + Send + Sync>,
+        right: Arc<dyn Shape + Send + Sync>,
+    ) -> Self {
+        Csg::new(CsgOp::Difference, left, right)
+    }
+
+    pub fn update_bounds(&mut self) {
+        let left = Group::child_bounds(self.left.as_ref());
+        let right = Group::child_bounds(self.right.as_ref());
+        self.bounds = match self.op {
+            CsgOp::Union => left.merge(&right),
+            CsgOp::Intersection => Bounds::new(
+                Vec4::point(
+                    left.min.x.max(right.min.x),
+                    left.min.y.max(right.min.y),
+                    left.min.z.max(right.min.z),
+                ),
+                Vec4::point(
+                    left.max.x.min(right.max.x),
+                    left.max.y.min(right.max.y),
+                    left.max.z.min(right.max.z),
+                ),
+            ),
+            CsgOp::Difference => left,
+        };
+    }
+
+    /// Keeps the intersections that lie on the surface of the combined shape.
+    /// Each one is paired with whether it came from the left operand, `xs`
+    /// has to be sorted.
+    pub fn filter_intersections<'a>(
+        &self,
+        xs: Vec<(Intersection<'a>, bool)>,
+    ) -> Vec<Intersection<'a>> {
+        let mut inl = false;
+        let mut inr = false;
+        let mut result = Vec::new();
+
+        let mut xs = xs.into_iter().peekable();
+        while let Some((i, mut lhit)) = xs.next() {
+            // hits at the same `t` are crossed together, so that operands
+            // sharing a surface don't depend on the order of their hits
+            let before = self.op.contains(inl, inr);
+            loop {
+                if lhit {
+                    inl = !inl;
+                } else {
+                    inr = !inr;
+                }
+                match xs.next_if(|(next, _)| next.t == i.t) {
+                    Some((_, next)) => lhit = next,
+                    None => break,
+                }
+            }
+            if before != self.op.contains(inl, inr) {
+                result.push(i);
+            }
+        }
+        result
+    }
+}
+
+impl Shape for Csg {
+    fn intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let local_ray = ray.transform(&self.inverse);
+        let xs = self
+            .local_intersect(&local_ray)
+            .into_iter()
+            .map(|i| i.with_parent(&self.inverse));
+        match &self.material {
+            Some(material) => xs.map(|i| i.with_material(material)).collect(),
+            None => xs.collect(),
+        }
+    }
+
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        if !self.bounds.intersection(ray) {
+            return vec![];
+        }
+        // the side is kept with each hit, both operands can be the same shape
+        let mut xs: Vec<_> = self.left.intersect(ray).into_iter().map(|i| (i, true)).collect();
+        xs.extend(self.right.intersect(ray).into_iter().map(|i| (i, false)));
+        xs.sort_by_key(|(i, _)| *i);
+        self.filter_intersections(xs)
+    }
+
+    fn normal_at(&self, _world_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("normal_at should never be called on a Csg");
+    }
+
+    fn local_normal_at(&self, _local_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("local_normal_at should never be called on a Csg");
+    }
+
+    fn transform(&self) -> &SqMatrix<4> {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.left.material())
+    }
+
+    fn set_transformation(&mut self, mat: SqMatrix<4>) {
+        self.transform = mat;
+        self.inverse = mat.inverse();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn inverse(&self) -> &Matrix<4, 4> {
+        &self.inverse
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Sphere;
+    use crate::color::Color;
+    use crate::shapes::cube::Cube;
+    use crate::shapes::cylinder::Cylinder;
+
+    #[test]
+    fn evaluating_rule_for_csg_operations() {
+        use CsgOp::*;
+        #[rustfmt::skip]
+        let cases = [
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ];
+        for (i, (op, lhit, inl, inr, expected)) in cases.into_iter().enumerate() {
+            assert_eq!(
+                op.intersection_allowed(lhit, inl, inr),
+                expected,
+                "Case {} failed: {:?} lhit={} inl={} inr={}",
+                i,
+                op,
+                lhit,
+                inl,
+                inr
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_list_of_intersections() {
+        let cases = [
+            (CsgOp::Union, 0, 3),
+            (CsgOp::Intersection, 1, 2),
+            (CsgOp::Difference, 0, 1),
+        ];
+        for (op, x0, x1) in cases {
+            let csg = Csg::new(op, Arc::new(Sphere::new()), Arc::new(Cube::new()));
+            let xs = vec![
+                (Intersection::new(1.0, csg.left.as_ref(), None, None), true),
+                (Intersection::new(2.0, csg.right.as_ref(), None, None), false),
+                (Intersection::new(3.0, csg.left.as_ref(), None, None), true),
+                (Intersection::new(4.0, csg.right.as_ref(), None, None), false),
+            ];
+            let result = csg.filter_intersections(xs.clone());
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].t, xs[x0].0.t);
+            assert_eq!(result[1].t, xs[x1].0.t);
+        }
+    }
+
+    #[test]
+    fn ray_misses_csg() {
+        let csg = Csg::union(Arc::new(Sphere::new()), Arc::new(Cube::new()));
+        let r = Ray::new(0.0, 2.0, -5.0, 0.0, 0.0, 1.0);
+        assert!(csg.intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn ray_hits_csg() {
+        let s1 = Sphere::new();
+        let id1 = s1.id;
+        let s2 = Sphere::with_transformation(Matrix::translation(0.0, 0.0, 0.5));
+        let id2 = s2.id;
+        let csg = Csg::union(Arc::new(s1), Arc::new(s2));
+
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let xs = csg.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - 4.0).abs() < 1e-5);
+        assert_eq!(xs[0].object.id(), id1);
+        assert!((xs[1].t - 6.5).abs() < 1e-5);
+        assert_eq!(xs[1].object.id(), id2);
+    }
+
+    #[test]
+    fn same_shape_on_both_sides() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        assert!(Csg::difference(s.clone(), s.clone()).intersect(&r).is_empty());
+        assert_eq!(Csg::intersection(s.clone(), s.clone()).intersect(&r).len(), 2);
+        assert_eq!(Csg::union(s.clone(), s).intersect(&r).len(), 2);
+    }
+
+    #[test]
+    fn material_override_leaves_shared_operands_alone() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let cube: Arc<dyn Shape + Send + Sync> = Arc::new(Cube::new());
+        let mut csg = Csg::union(s.clone(), cube);
+        let mut material = Material::default();
+        material.color = Color::red();
+        csg.set_material(material);
+
+        let xs = csg.intersect(&Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0));
+        assert_eq!(xs[0].material().color, Color::red());
+        assert_eq!(csg.material().color, Color::red());
+        assert_eq!(s.material().color, Color::white());
+    }
+
+    #[test]
+    fn cube_with_drilled_hole() {
+        let mut drill = Group::new();
+        drill.add_child(Arc::new(Cylinder::truncated(-2.0, 2.0, true)));
+        drill.set_transformation(Matrix::scaling(0.5, 1.0, 0.5));
+        let csg = Csg::difference(Arc::new(Cube::new()), Arc::new(drill));
+
+        // straight down the hole
+        let r = Ray::new(0.0, 5.0, 0.0, 0.0, -1.0, 0.0);
+        assert!(csg.intersect(&r).is_empty());
+
+        // through the wall of the hole
+        let r = Ray::new(-5.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let xs = csg.intersect(&r);
+        let ts = xs.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts.len(), 4);
+        for (t, expected) in ts.iter().zip([4.0, 4.5, 5.5, 6.0]) {
+            assert!((t - expected).abs() < 1e-5);
+        }
+
+        // beside the hole
+        let r = Ray::new(0.8, 5.0, 0.0, 0.0, -1.0, 0.0);
+        assert_eq!(csg.intersect(&r).len(), 2);
+    }
+
+    #[test]
+    fn csg_bounds_from_operands() {
+        let left = Cube::new();
+        let right = Cube::with_transformation(Matrix::translation(1.0, 0.0, 0.0));
+        let csg = Csg::union(Arc::new(left), Arc::new(right));
+        assert_eq!(
+            csg.bounds(),
+            Bounds::new(Vec4::point(-1.0, -1.0, -1.0), Vec4::point(2.0, 1.0, 1.0))
+        );
+
+        let left = Cube::new();
+        let right = Cube::with_transformation(Matrix::translation(1.0, 0.0, 0.0));
+        let mut csg = Csg::intersection(Arc::new(left), Arc::new(right));
+        assert_eq!(
+            csg.bounds(),
+            Bounds::new(Vec4::point(0.0, -1.0, -1.0), Vec4::point(1.0, 1.0, 1.0))
+        );
+
+        csg.set_transformation(Matrix::translation(0.0, 5.0, 0.0));
+        assert_eq!(
+            Group::child_bounds(&csg),
+            Bounds::new(Vec4::point(0.0, 4.0, -1.0), Vec4::point(1.0, 6.0, 1.0))
+        );
+        let r = Ray::new(0.5, 10.0, 0.0, 0.0, -1.0, 0.0);
+        let xs = csg.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - 4.0).abs() < 1e-5);
+    }
+}
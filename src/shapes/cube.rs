@@ -34,7 +34,7 @@ impl Cube {
         let id = next_shape_id();
         Cube {
             id,
-            transform: mat.clone(),
+            transform: mat,
             material: Material::default(),
             inverse: mat.inverse(),
             bounds: Bounds::new(Vec4::point(-1.0, -1.0, -1.0), Vec4::point(1.0, 1.0, 1.0)),
@@ -101,7 +101,7 @@ impl Shape for Cube {
     }
 
     fn set_transformation(&mut self, mat: Matrix<4, 4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
 
@@ -0,0 +1,261 @@
+use super::{Shape, next_shape_id};
+use crate::bounds::Bounds;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::math::EPSILON;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::ray::Ray;
+use crate::vec4::Vec4;
+
+#[derive(Debug)]
+pub struct Cylinder {
+    pub id: usize,
+    pub transform: SqMatrix<4>,
+    pub material: Material,
+    pub inverse: SqMatrix<4>,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub closed: bool,
+}
+
+impl Cylinder {
+    pub fn new() -> Cylinder {
+        Cylinder::truncated(f64::NEG_INFINITY, f64::INFINITY, false)
+    }
+    pub fn truncated(minimum: f64, maximum: f64, closed: bool) -> Cylinder {
+        Cylinder {
+            id: next_shape_id(),
+            transform: Matrix::eye(),
+            material: Material::default(),
+            inverse: Matrix::eye(),
+            minimum,
+            maximum,
+            closed,
+        }
+    }
+    pub fn with_transformation(mat: Matrix<4, 4>) -> Self {
+        let mut cyl = Cylinder::new();
+        cyl.set_transformation(mat);
+        cyl
+    }
+
+    // a point is within the cap if it lies inside the unit radius at y
+    fn check_cap(ray: &Ray, t: f64) -> bool {
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        (x.powi(2) + z.powi(2)) <= 1.0
+    }
+
+    fn intersect_caps<'a>(&'a self, ray: &Ray, xs: &mut Vec<Intersection<'a>>) {
+        if !self.closed || ray.direction.y.abs() < EPSILON {
+            return;
+        }
+        let t = (self.minimum - ray.origin.y) / ray.direction.y;
+        if Cylinder::check_cap(ray, t) {
+            xs.push(Intersection::new(t, self, None, None));
+        }
+        let t = (self.maximum - ray.origin.y) / ray.direction.y;
+        if Cylinder::check_cap(ray, t) {
+            xs.push(Intersection::new(t, self, None, None));
+        }
+    }
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shape for Cylinder {
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let mut xs = Vec::new();
+        let a = ray.direction.x.powi(2) + ray.direction.z.powi(2);
+
+        // rays parallel to the y axis can only hit the caps
+        if a.abs() >= EPSILON {
+            let b = 2.0 * ray.origin.x * ray.direction.x + 2.0 * ray.origin.z * ray.direction.z;
+            let c = ray.origin.x.powi(2) + ray.origin.z.powi(2) - 1.0;
+            let disc = b.powi(2) - 4.0 * a * c;
+            if disc < 0.0 {
+                return xs;
+            }
+
+            let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
+            let mut t1 = (-b + disc.sqrt()) / (2.0 * a);
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            for t in [t0, t1] {
+                let y = ray.origin.y + t * ray.direction.y;
+                if self.minimum < y && y < self.maximum {
+                    xs.push(Intersection::new(t, self, None, None));
+                }
+            }
+        }
+
+        self.intersect_caps(ray, &mut xs);
+        xs
+    }
+
+    fn local_normal_at(&self, local_point: Vec4, _i: &Intersection) -> Vec4 {
+        let dist = local_point.x.powi(2) + local_point.z.powi(2);
+        if dist < 1.0 && local_point.y >= self.maximum - EPSILON {
+            Vec4::vector(0.0, 1.0, 0.0)
+        } else if dist < 1.0 && local_point.y <= self.minimum + EPSILON {
+            Vec4::vector(0.0, -1.0, 0.0)
+        } else {
+            Vec4::vector(local_point.x, 0.0, local_point.z)
+        }
+    }
+
+    fn transform(&self) -> &SqMatrix<4> {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn set_transformation(&mut self, mat: Matrix<4, 4>) {
+        self.transform = mat;
+        self.inverse = mat.inverse();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = material;
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn inverse(&self) -> &Matrix<4, 4> {
+        &self.inverse
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn bounds(&self) -> Bounds {
+        Bounds::new(
+            Vec4::point(-1.0, self.minimum, -1.0),
+            Vec4::point(1.0, self.maximum, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_misses_cylinder() {
+        let cyl = Cylinder::new();
+        let cases = [
+            (Vec4::point(1.0, 0.0, 0.0), Vec4::vector(0.0, 1.0, 0.0)),
+            (Vec4::point(0.0, 0.0, 0.0), Vec4::vector(0.0, 1.0, 0.0)),
+            (Vec4::point(0.0, 0.0, -5.0), Vec4::vector(1.0, 1.0, 1.0)),
+        ];
+        for (origin, direction) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            assert_eq!(cyl.local_intersect(&r).len(), 0);
+        }
+    }
+
+    #[test]
+    fn ray_hits_cylinder() {
+        let cyl = Cylinder::new();
+        let cases = [
+            (
+                Vec4::point(1.0, 0.0, -5.0),
+                Vec4::vector(0.0, 0.0, 1.0),
+                5.0,
+                5.0,
+            ),
+            (
+                Vec4::point(0.0, 0.0, -5.0),
+                Vec4::vector(0.0, 0.0, 1.0),
+                4.0,
+                6.0,
+            ),
+            (
+                Vec4::point(0.5, 0.0, -5.0),
+                Vec4::vector(0.1, 1.0, 1.0),
+                6.80798,
+                7.08872,
+            ),
+        ];
+        for (origin, direction, t0, t1) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            let xs = cyl.local_intersect(&r);
+            assert_eq!(xs.len(), 2);
+            assert!((xs[0].t - t0).abs() < EPSILON);
+            assert!((xs[1].t - t1).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn truncated_and_capped_cylinder() {
+        let cyl = Cylinder::truncated(1.0, 2.0, false);
+        let cases = [
+            (Vec4::point(0.0, 1.5, 0.0), Vec4::vector(0.1, 1.0, 0.0), 0),
+            (Vec4::point(0.0, 3.0, -5.0), Vec4::vector(0.0, 0.0, 1.0), 0),
+            (Vec4::point(0.0, 0.0, -5.0), Vec4::vector(0.0, 0.0, 1.0), 0),
+            (Vec4::point(0.0, 2.0, -5.0), Vec4::vector(0.0, 0.0, 1.0), 0),
+            (Vec4::point(0.0, 1.0, -5.0), Vec4::vector(0.0, 0.0, 1.0), 0),
+            (Vec4::point(0.0, 1.5, -2.0), Vec4::vector(0.0, 0.0, 1.0), 2),
+        ];
+        for (origin, direction, count) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            assert_eq!(cyl.local_intersect(&r).len(), count);
+        }
+
+        let cyl = Cylinder::truncated(1.0, 2.0, true);
+        let cases = [
+            (Vec4::point(0.0, 3.0, 0.0), Vec4::vector(0.0, -1.0, 0.0), 2),
+            (Vec4::point(0.0, 3.0, -2.0), Vec4::vector(0.0, -1.0, 2.0), 2),
+            (Vec4::point(0.0, 4.0, -2.0), Vec4::vector(0.0, -1.0, 1.0), 2),
+            (Vec4::point(0.0, 0.0, -2.0), Vec4::vector(0.0, 1.0, 2.0), 2),
+            (Vec4::point(0.0, -1.0, -2.0), Vec4::vector(0.0, 1.0, 1.0), 2),
+        ];
+        for (origin, direction, count) in cases {
+            let r = Ray::from_vec4(origin, direction.norm());
+            assert_eq!(cyl.local_intersect(&r).len(), count);
+        }
+    }
+
+    #[test]
+    fn cylinder_normals() {
+        let cyl = Cylinder::truncated(1.0, 2.0, true);
+        let dummy = Cylinder::new();
+        let i = Intersection::new(0.0, &dummy, None, None);
+        let cases = [
+            (Vec4::point(1.0, 1.5, 0.0), Vec4::vector(1.0, 0.0, 0.0)),
+            (Vec4::point(0.0, 1.5, -1.0), Vec4::vector(0.0, 0.0, -1.0)),
+            (Vec4::point(0.0, 1.0, 0.0), Vec4::vector(0.0, -1.0, 0.0)),
+            (Vec4::point(0.5, 1.0, 0.0), Vec4::vector(0.0, -1.0, 0.0)),
+            (Vec4::point(0.0, 2.0, 0.0), Vec4::vector(0.0, 1.0, 0.0)),
+            (Vec4::point(0.0, 2.0, 0.5), Vec4::vector(0.0, 1.0, 0.0)),
+        ];
+        for (point, normal) in cases {
+            assert_eq!(cyl.local_normal_at(point, &i), normal);
+        }
+    }
+
+    #[test]
+    fn cylinder_bounds() {
+        assert!(!Cylinder::new().bounds().is_finite());
+        let cyl = Cylinder::truncated(-5.0, 3.0, true);
+        assert_eq!(
+            cyl.bounds(),
+            Bounds::new(Vec4::point(-1.0, -5.0, -1.0), Vec4::point(1.0, 3.0, 1.0))
+        );
+    }
+}
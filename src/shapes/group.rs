@@ -1,9 +1,11 @@
 use std::f64::INFINITY;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use super::{Shape, next_shape_id};
 use crate::bounds::Bounds;
+use crate::bvh::Bvh;
 use crate::intersection::Intersection;
+use crate::material::Material;
 use crate::matrix::{Matrix, SqMatrix};
 use crate::ray::Ray;
 use crate::vec4::Vec4;
@@ -11,10 +13,15 @@ use crate::vec4::Vec4;
 #[derive(Debug)]
 pub struct Group {
     pub id: usize,
-    pub children: Vec<Arc<dyn Shape + Send + Sync>>,
+    children: Vec<Arc<dyn Shape + Send + Sync>>,
     pub transfom: SqMatrix<4>,
     pub inverse: SqMatrix<4>,
-    pub bounds: Bounds
+    pub bounds: Bounds,
+    // built on the first intersection after the children changed
+    bvh: OnceLock<Bvh>,
+    /// Replaces the material of every child when set, the children are not
+    /// modified so they can be shared with other groups.
+    pub material: Option<Material>,
 }
 
 impl Group {
@@ -24,29 +31,49 @@ impl Group {
             children: Vec::new(),
             transfom: Matrix::eye(),
             inverse: Matrix::eye(),
-            bounds: Bounds::new(Vec4::point(-INFINITY, -INFINITY,-INFINITY), Vec4::point(INFINITY, INFINITY, INFINITY))
+            bounds: Bounds::new(Vec4::point(-INFINITY, -INFINITY,-INFINITY), Vec4::point(INFINITY, INFINITY, INFINITY)),
+            bvh: OnceLock::new(),
+            material: None,
         }
         
     }
-    pub fn add_child(&mut self, mut shape: Arc<dyn Shape + Send + Sync>) {
-        let combined = &self.transfom * shape.transform();
-        let shape_mut =
-            Arc::get_mut(&mut shape).expect("Shape Arc was already cloned; add it only once");
-        shape_mut.set_transformation(combined);
+    pub fn children(&self) -> &[Arc<dyn Shape + Send + Sync>] {
+        &self.children
+    }
+
+    /// Grows the bounding box to fit the child. The BVH is dropped and
+    /// rebuilt by the next intersection.
+    pub fn add_child(&mut self, shape: Arc<dyn Shape + Send + Sync>) {
+        self.bounds = self.bounds.merge(&Group::child_bounds(shape.as_ref()));
         self.children.push(shape);
-        self.update_bounds();
+        self.bvh.take();
     }
 
-    pub fn add_child_without_bounds(&mut self, mut shape: Arc<dyn Shape + Send + Sync>) {
-        let combined = &self.transfom * shape.transform();
-        let shape_mut =
-            Arc::get_mut(&mut shape).expect("Shape Arc was already cloned; add it only once");
-        shape_mut.set_transformation(combined);
+    /// Adds a child without growing the bounding box, call `update_bounds`
+    /// once after adding a batch of children.
+    pub fn add_child_without_bounds(&mut self, shape: Arc<dyn Shape + Send + Sync>) {
         self.children.push(shape);
+        self.bvh.take();
     }
 
+    /// Recomputes the bounding box and builds the BVH over all children.
     pub fn update_bounds(&mut self) {
         self.bounds = self.bounds();
+        self.bvh = OnceLock::from(self.build_bvh());
+    }
+
+    fn build_bvh(&self) -> Bvh {
+        let child_bounds = self
+            .children
+            .iter()
+            .map(|child| Group::child_bounds(child.as_ref()))
+            .collect::<Vec<_>>();
+        Bvh::build(&child_bounds)
+    }
+
+    /// Bounds of a child in the space of its parent.
+    pub fn child_bounds(child: &dyn Shape) -> Bounds {
+        child.bounds().transform(child.transform())
     }
 }
 
@@ -55,11 +82,15 @@ impl Shape for Group {
         self.id
     }
 
-    fn local_intersect<'a>(
-        &'a self,
-        _ray: &crate::ray::Ray,
-    ) -> Vec<crate::intersection::Intersection<'a>> {
-        panic!("local_intersect should never be called on a Group")
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let mut xs = Vec::new();
+        if !self.bounds.intersection(ray) {
+            return xs;
+        }
+        self.bvh
+            .get_or_init(|| self.build_bvh())
+            .traverse(ray, |i| xs.extend(self.children[i].intersect(ray)));
+        xs
     }
 
     fn local_normal_at(
@@ -71,7 +102,9 @@ impl Shape for Group {
     }
 
     fn material(&self) -> &crate::material::Material {
-        self.children[0].material()
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.children[0].material())
     }
 
     fn normal_at(&self, _world_point: crate::vec4::Vec4, _i: &Intersection) -> crate::vec4::Vec4 {
@@ -79,33 +112,12 @@ impl Shape for Group {
     }
 
     fn set_material(&mut self, material: crate::material::Material) {
-        for child in &mut self.children {
-            let child_mut = Arc::get_mut(child)
-                .expect("Child Arc was cloned elsewhere; ensure unique ownership");
-            child_mut.set_material(material.clone());
-        }
+        self.material = Some(material);
     }
 
     fn set_transformation(&mut self, mat: SqMatrix<4>) {
-        let old_group = self.transfom.clone();
-        let new_group = mat.clone();
-        let delta = new_group * old_group.inverse();
-
-        for child in &mut self.children {
-            let child_mut = Arc::get_mut(child)
-                .expect("Child Arc was cloned elsewhere; ensure unique ownership");
-
-            if let Some(group) = child_mut.as_any_mut().downcast_mut::<Group>() {
-                group.set_transformation(delta.clone());
-            } else {
-                let child_transform = child_mut.transform();
-                let new_child_transform = &delta * child_transform;
-                child_mut.set_transformation(new_child_transform);
-            }
-        }
-        self.transfom = mat.clone();
+        self.transfom = mat;
         self.inverse = mat.inverse();
-        self.update_bounds();
     }
 
     fn transform(&self) -> &SqMatrix<4> {
@@ -113,14 +125,15 @@ impl Shape for Group {
     }
 
     fn intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
-        let mut xs = Vec::new();
-        if !self.bounds.intersection(&ray) {
-            return vec![];
-        }
-        for child in &self.children {
-            xs.extend(child.intersect(&ray));
+        let local_ray = ray.transform(&self.inverse);
+        let xs = self
+            .local_intersect(&local_ray)
+            .into_iter()
+            .map(|i| i.with_parent(&self.inverse));
+        match &self.material {
+            Some(material) => xs.map(|i| i.with_material(material)).collect(),
+            None => xs.collect(),
         }
-        xs
     }
 
     fn inverse(&self) -> &Matrix<4, 4> {
@@ -144,11 +157,7 @@ impl Shape for Group {
         let mut z_max = f64::NEG_INFINITY;
     
         for child in &self.children {
-            let b = if child.as_any().is::<Group>() {
-                child.bounds()
-            } else {
-                child.bounds().transform(child.transform())
-            };
+            let b = Group::child_bounds(child.as_ref());
     
             if !b.is_finite() {
                 continue;
@@ -171,11 +180,12 @@ impl Shape for Group {
 
 #[cfg(test)]
 pub mod tests {
+    use std::f64::consts::PI;
     use std::sync::Arc;
 
-    use crate::{Sphere, matrix::Matrix, ray::Ray, shapes::Shape};
+    use crate::{Sphere, color::Color, matrix::Matrix, ray::Ray, shapes::Shape, vec4::Vec4};
 
-    use super::Group;
+    use super::{Group, Material};
 
     #[test]
     fn add_to_group() {
@@ -218,46 +228,165 @@ pub mod tests {
         assert_eq!(xs[2].object.id(), id1);
         assert_eq!(xs[3].object.id(), id1);
     }
-    //#[test]
-    //fn intersect_with_trans_group() {
-    //    let mut g = Group::new();
-    //    let group_trans = Matrix::scaling(2.0, 2.0, 2.0);
-    //    g.set_transformation(group_trans.clone());
+    #[test]
+    fn intersect_large_group_through_bvh() {
+        let mut g = Group::new();
+        let mut ids = Vec::new();
+        for i in 0..200 {
+            let s = Sphere::with_transformation(Matrix::translation(i as f64 * 3.0, 0.0, 0.0));
+            ids.push(s.id);
+            g.add_child_without_bounds(Arc::new(s));
+        }
+        g.update_bounds();
+        assert_eq!(g.bvh.get().unwrap().len(), 200);
+
+        let r = Ray::new(150.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].object.id(), ids[50]);
+
+        let r = Ray::new(-5.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 400);
+    }
+
+    #[test]
+    fn children_added_after_build_are_intersected() {
+        let mut g = Group::new();
+        g.add_child(Arc::new(Sphere::new()));
+        let s = Sphere::with_transformation(Matrix::translation(0.0, 0.0, 5.0));
+        let id = s.id;
+        g.add_child_without_bounds(Arc::new(s));
+
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let mut xs = g.intersect(&r);
+        xs.sort();
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[3].object.id(), id);
+
+        // add_child grows the bounds of a built group and drops its BVH, so
+        // the new child is neither culled nor skipped
+        g.update_bounds();
+        let s = Sphere::with_transformation(Matrix::translation(5.0, 0.0, 0.0));
+        let id = s.id;
+        g.add_child(Arc::new(s));
+        assert!(g.bvh.get().is_none());
+        let xs = g.intersect(&Ray::new(5.0, 0.0, -5.0, 0.0, 0.0, 1.0));
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].object.id(), id);
+        assert_eq!(g.bvh.get().unwrap().len(), 3);
+    }
 
-    //    let mut s = Sphere::new();
+    #[test]
+    fn intersect_with_trans_group() {
+        let mut g = Group::new();
+        let group_trans = Matrix::scaling(2.0, 2.0, 2.0);
+        g.set_transformation(group_trans);
 
-    //    let sphere_trans = Matrix::translation(5.0, 0.0, 0.0);
+        let mut s = Sphere::new();
 
-    //    s.set_transformation(sphere_trans.clone());
+        let sphere_trans = Matrix::translation(5.0, 0.0, 0.0);
 
-    //    g.add_child(Arc::new(s));
+        s.set_transformation(sphere_trans);
 
-    //    let post_child_transform = g.children[0].transform();
-    //    let post_child_inverse = g.children[0].inverse();
-    //    assert_eq!(post_child_transform.inverse(), post_child_inverse.clone());
-    //    assert_eq!(group_trans * sphere_trans, post_child_transform.clone());
+        g.add_child(Arc::new(s));
 
-    //    let r = Ray::new(10.0, 0.0, -10.0, 0.0, 0.0, 1.0);
-    //    let mut xs = g.intersect(&r);
-    //    xs.sort();
-    //    println!("{:?}", xs);
-    //    assert_eq!(xs.len(), 2);
-    //}
+        // the child keeps its own transform, the group applies its own on top
+        let post_child_transform = g.children[0].transform();
+        let post_child_inverse = g.children[0].inverse();
+        assert_eq!(post_child_transform.inverse(), post_child_inverse.clone());
+        assert_eq!(sphere_trans, post_child_transform.clone());
 
-    //#[test]
-    //fn convert_from_wold_to_object_space() {
-    //    let mut g1 = Group::new();
-    //    g1.set_transformation(Matrix::rotation_y(PI/2.0));
-    //    let mut g2 = Group::new();
-    //    g2.set_transformation(Matrix::scaling(1.0, 2.0, 3.0));
+        let r = Ray::new(10.0, 0.0, -10.0, 0.0, 0.0, 1.0);
+        let mut xs = g.intersect(&r);
+        xs.sort();
+        assert_eq!(xs.len(), 2);
+    }
 
-    //    let mut s = Sphere::new();
-    //    s.set_transformation(Matrix::translation(5.0, 0.0, 0.0));
+    #[test]
+    fn convert_from_wold_to_object_space() {
+        let mut g1 = Group::new();
+        g1.set_transformation(Matrix::rotation_y(PI / 2.0));
+        let mut g2 = Group::new();
+        g2.set_transformation(Matrix::scaling(2.0, 2.0, 2.0));
+
+        let mut s = Sphere::new();
+        s.set_transformation(Matrix::translation(5.0, 0.0, 0.0));
+
+        g2.add_child(Arc::new(s));
+        g1.add_child(Arc::new(g2));
+
+        let r = Ray::new(-5.0, 0.0, -10.0, 1.0, 0.0, 0.0);
+        let xs = g1.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        let world_point = Vec4::point(-2.0, 0.0, -10.0);
+        let object_point = xs[0].object.world_to_object(&world_point, &xs[0]);
+        assert_eq!(object_point, Vec4::point(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn normal_on_child_object() {
+        let mut g1 = Group::new();
+        g1.set_transformation(Matrix::rotation_y(PI / 2.0));
+        let mut g2 = Group::new();
+        g2.set_transformation(Matrix::scaling(1.0, 2.0, 3.0));
+
+        let mut s = Sphere::new();
+        s.set_transformation(Matrix::translation(5.0, 0.0, 0.0));
+
+        g2.add_child(Arc::new(s));
+        g1.add_child(Arc::new(g2));
+
+        let r = Ray::new(0.0, 0.0, -20.0, 0.0, 0.0, 1.0);
+        let xs = g1.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        let world_point = Vec4::point(1.7321, 1.1547, -5.5774);
+        let n = xs[0].object.normal_at(world_point, &xs[0]);
+        assert_eq!(n, Vec4::vector(0.2857, 0.4286, -0.8571));
+    }
+
+    #[test]
+    fn shared_child_in_several_groups() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let mut parent = Group::new();
+        for x in [-3.0, 3.0] {
+            let mut g = Group::new();
+            g.add_child(Arc::clone(&s));
+            g.set_transformation(Matrix::translation(x, 0.0, 0.0));
+            parent.add_child(Arc::new(g));
+        }
+        assert_eq!(Arc::strong_count(&s), 3);
+        assert_eq!(s.transform().clone(), Matrix::eye());
 
-    //    g2.add_child(Arc::new(s));
-    //    g1.add_child(Arc::new(g2));
+        let r = Ray::new(-10.0, 0.0, 0.0, 1.0, 0.0, 0.0);
+        let mut xs = parent.intersect(&r);
+        xs.sort();
+        let ts = xs.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts, vec![6.0, 8.0, 12.0, 14.0]);
+        assert!(xs.iter().all(|i| i.object.id() == s.id()));
+
+        let comps = xs[2].prepare_computations(&r, &xs);
+        assert_eq!(comps.normalv, Vec4::vector(-1.0, 0.0, 0.0));
+        assert_eq!(comps.object_point, Vec4::point(-1.0, 0.0, 0.0));
+    }
 
-    //    let world_point = Vec4::point(1.7321, 1.1547, -5.5774);
-    //
-    //}
+    #[test]
+    fn material_of_group_with_shared_children() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let mut red = Group::new();
+        red.add_child(Arc::clone(&s));
+        let mut material = Material::default();
+        material.color = Color::red();
+        red.set_material(material);
+        let mut plain = Group::new();
+        plain.add_child(Arc::clone(&s));
+
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let xs = red.intersect(&r);
+        assert_eq!(xs[0].material().color, Color::red());
+        assert_eq!(red.material().color, Color::red());
+        let xs = plain.intersect(&r);
+        assert_eq!(xs[0].material().color, Color::white());
+        assert_eq!(s.material().color, Color::white());
+    }
 }
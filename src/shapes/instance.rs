@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use super::group::Group;
+use super::{Shape, next_shape_id};
+use crate::bounds::Bounds;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::ray::Ray;
+use crate::vec4::Vec4;
+
+/// Places a shared shape in the scene with its own transform and an optional
+/// material override. The wrapped shape is never modified, so the same `Arc`
+/// can back any number of instances.
+#[derive(Debug)]
+pub struct Instance {
+    pub id: usize,
+    pub shape: Arc<dyn Shape + Send + Sync>,
+    pub transform: SqMatrix<4>,
+    pub inverse: SqMatrix<4>,
+    pub material: Option<Material>,
+    pub bounds: Bounds,
+}
+
+impl Instance {
+    pub fn new(shape: Arc<dyn Shape + Send + Sync>) -> Self {
+        let bounds = Group::child_bounds(shape.as_ref());
+        Self {
+            id: next_shape_id(),
+            shape,
+            transform: Matrix::eye(),
+            inverse: Matrix::eye(),
+            material: None,
+            bounds,
+        }
+    }
+    pub fn with_transformation(shape: Arc<dyn Shape + Send + Sync>, mat: SqMatrix<4>) -> Self {
+        let mut instance = Instance::new(shape);
+        instance.set_transformation(mat);
+        instance
+    }
+}
+
+impl Shape for Instance {
+    fn intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let local_ray = ray.transform(&self.inverse);
+        let xs = self
+            .local_intersect(&local_ray)
+            .into_iter()
+            .map(|i| i.with_parent(&self.inverse));
+        match &self.material {
+            Some(material) => xs.map(|i| i.with_material(material)).collect(),
+            None => xs.collect(),
+        }
+    }
+
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        if !self.bounds.intersection(ray) {
+            return vec![];
+        }
+        self.shape.intersect(ray)
+    }
+
+    fn normal_at(&self, _world_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("normal_at should never be called on an Instance");
+    }
+
+    fn local_normal_at(&self, _local_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("local_normal_at should never be called on an Instance");
+    }
+
+    fn transform(&self) -> &SqMatrix<4> {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.shape.material())
+    }
+
+    fn set_transformation(&mut self, mat: SqMatrix<4>) {
+        self.transform = mat;
+        self.inverse = mat.inverse();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn inverse(&self) -> &Matrix<4, 4> {
+        &self.inverse
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::Sphere;
+    use crate::color::Color;
+    use crate::light::PointLight;
+    use crate::shapes::triangle::Triangle;
+    use crate::world::World;
+
+    #[test]
+    fn instances_share_one_shape() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let a = Instance::with_transformation(Arc::clone(&s), Matrix::translation(-3.0, 0.0, 0.0));
+        let b = Instance::with_transformation(Arc::clone(&s), Matrix::translation(3.0, 0.0, 0.0));
+        assert_eq!(Arc::strong_count(&s), 3);
+
+        let r = Ray::new(-3.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        assert_eq!(a.intersect(&r).len(), 2);
+        assert!(b.intersect(&r).is_empty());
+
+        let xs = b.intersect(&Ray::new(3.0, 0.0, -5.0, 0.0, 0.0, 1.0));
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].object.id(), s.id());
+        assert_eq!(
+            xs[0].object.normal_at(Vec4::point(3.0, 0.0, -1.0), &xs[0]),
+            Vec4::vector(0.0, 0.0, -1.0)
+        );
+        assert_eq!(
+            Group::child_bounds(&b),
+            Bounds::new(Vec4::point(2.0, -1.0, -1.0), Vec4::point(4.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn instance_overrides_material() {
+        let mut mesh = Group::new();
+        mesh.add_child(Arc::new(Triangle::new(
+            Vec4::point(0.0, 1.0, 0.0),
+            Vec4::point(-1.0, 0.0, 0.0),
+            Vec4::point(1.0, 0.0, 0.0),
+        )));
+        let mesh: Arc<dyn Shape + Send + Sync> = Arc::new(mesh);
+
+        let plain = Instance::new(Arc::clone(&mesh));
+        let mut red = Instance::with_transformation(mesh, Matrix::translation(0.0, 0.0, 2.0));
+        let mut material = Material::default();
+        material.color = Color::red();
+        red.set_material(material);
+
+        let r = Ray::new(0.0, 0.5, -5.0, 0.0, 0.0, 1.0);
+        let xs = plain.intersect(&r);
+        assert_eq!(xs[0].material().color, Color::white());
+        let xs = red.intersect(&r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 7.0);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert_eq!(comps.material.color, Color::red());
+    }
+
+    #[test]
+    fn many_instances_in_world() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let mut w = World::new(PointLight::new(
+            Vec4::point(-10.0, 10.0, -10.0),
+            Color::white(),
+        ));
+        for i in 0..1000 {
+            let mut instance = Instance::with_transformation(
+                Arc::clone(&s),
+                Matrix::translation(i as f64 * 3.0, 0.0, 0.0),
+            );
+            let mut material = Material::default();
+            material.color = Color::new(0.0, 0.0, 1.0);
+            instance.set_material(material);
+            w.add_shape(Arc::new(instance));
+        }
+        assert_eq!(Arc::strong_count(&s), 1001);
+
+        let r = Ray::new(300.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let c = w.color_at(&r, 1, &mut rand::rng());
+        assert!(c.b > 0.1 && c.r.abs() < 1e-9 && c.g.abs() < 1e-9);
+    }
+}
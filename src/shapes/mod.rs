@@ -8,8 +8,13 @@ use std::any::Any;
 use std::fmt::Debug;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod cone;
+pub mod csg;
 pub mod cube;
+pub mod cylinder;
 pub mod group;
+pub mod instance;
+pub mod motion;
 pub mod plane;
 pub mod smooth_triangle;
 pub mod sphere;
@@ -28,10 +33,27 @@ pub trait Shape: Debug + Sync + Send + Any {
     }
     fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>>;
     fn normal_at(&self, world_point: Vec4, i: &Intersection) -> Vec4 {
-        let local_point = self.inverse() * &world_point;
+        let local_point = self.world_to_object(&world_point, i);
         let local_normal = self.local_normal_at(local_point, i);
-        let mut world_normal = self.inverse().transpose() * local_normal;
+        self.normal_to_world(local_normal, i)
+    }
+    /// Converts a world space point into object space, through all groups the
+    /// intersection `i` was found in.
+    fn world_to_object(&self, world_point: &Vec4, i: &Intersection) -> Vec4 {
+        match &i.world_to_parent {
+            Some(m) => self.inverse() * &(m * world_point),
+            None => self.inverse() * world_point,
+        }
+    }
+    /// Converts an object space normal back into world space, through all groups
+    /// the intersection `i` was found in.
+    fn normal_to_world(&self, normal: Vec4, i: &Intersection) -> Vec4 {
+        let mut world_normal = self.inverse().transpose() * normal;
         world_normal.w = 0.0;
+        if let Some(m) = &i.world_to_parent {
+            world_normal = m.transpose() * world_normal;
+            world_normal.w = 0.0;
+        }
         world_normal.norm()
     }
     fn local_normal_at(&self, local_point: Vec4, i: &Intersection) -> Vec4;
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use super::group::Group;
+use super::{Shape, next_shape_id};
+use crate::bounds::Bounds;
+use crate::intersection::Intersection;
+use crate::material::Material;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::ray::Ray;
+use crate::transform::Decomposed;
+use crate::vec4::Vec4;
+
+// steps per keyframe segment used to sweep the bounds
+const BOUNDS_STEPS: usize = 32;
+
+/// Moves a shape over time. The transform at a ray's `time` is interpolated
+/// between keyframes, before the first and after the last keyframe the shape
+/// stands still. `transform` is applied on top of the animated transform and
+/// the bounds cover the whole motion.
+#[derive(Debug)]
+pub struct Motion {
+    pub id: usize,
+    pub shape: Arc<dyn Shape + Send + Sync>,
+    keyframes: Vec<(f64, Decomposed)>,
+    pub transform: SqMatrix<4>,
+    pub inverse: SqMatrix<4>,
+    pub bounds: Bounds,
+    /// Replaces the material of the moving shape when set.
+    pub material: Option<Material>,
+}
+
+impl Motion {
+    /// Moves from `start` at time 0 to `end` at time 1.
+    pub fn new(shape: Arc<dyn Shape + Send + Sync>, start: SqMatrix<4>, end: SqMatrix<4>) -> Self {
+        Motion::with_keyframes(shape, vec![(0.0, start), (1.0, end)])
+    }
+    pub fn with_keyframes(
+        shape: Arc<dyn Shape + Send + Sync>,
+        mut keyframes: Vec<(f64, SqMatrix<4>)>,
+    ) -> Self {
+        assert!(!keyframes.is_empty(), "Motion needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut motion = Motion {
+            id: next_shape_id(),
+            shape,
+            keyframes: keyframes
+                .iter()
+                .map(|(time, mat)| (*time, Decomposed::new(mat)))
+                .collect(),
+            transform: Matrix::eye(),
+            inverse: Matrix::eye(),
+            bounds: Bounds::empty(),
+            material: None,
+        };
+        motion.update_bounds();
+        motion
+    }
+
+    /// Animated transform at `time`, without `transform`.
+    pub fn transform_at(&self, time: f64) -> SqMatrix<4> {
+        let (first, last) = (
+            &self.keyframes[0],
+            &self.keyframes[self.keyframes.len() - 1],
+        );
+        if time <= first.0 {
+            return first.1.matrix();
+        }
+        if time >= last.0 {
+            return last.1.matrix();
+        }
+        let next = self.keyframes.partition_point(|(t, _)| *t <= time);
+        let ((t0, a), (t1, b)) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        a.lerp(b, (time - t0) / (t1 - t0)).matrix()
+    }
+
+    pub fn update_bounds(&mut self) {
+        let child = Group::child_bounds(self.shape.as_ref());
+        let mut bounds = child.transform(&self.keyframes[0].1.matrix());
+        for pair in self.keyframes.windows(2) {
+            let ((t0, a), (t1, b)) = (&pair[0], &pair[1]);
+            if t1 == t0 {
+                continue;
+            }
+            for step in 1..=BOUNDS_STEPS {
+                let t = step as f64 / BOUNDS_STEPS as f64;
+                bounds = bounds.merge(&child.transform(&a.lerp(b, t).matrix()));
+            }
+        }
+        self.bounds = bounds;
+    }
+}
+
+impl Shape for Motion {
+    fn intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        let local_ray = ray.transform(&self.inverse);
+        if !self.bounds.intersection(&local_ray) {
+            return vec![];
+        }
+        let inverse = self.transform_at(ray.time).inverse();
+        let world_to_shape = inverse * self.inverse;
+        let xs = self
+            .local_intersect(&local_ray.transform(&inverse))
+            .into_iter()
+            .map(|i| i.with_parent(&world_to_shape));
+        match &self.material {
+            Some(material) => xs.map(|i| i.with_material(material)).collect(),
+            None => xs.collect(),
+        }
+    }
+
+    fn local_intersect<'a>(&'a self, ray: &Ray) -> Vec<Intersection<'a>> {
+        self.shape.intersect(ray)
+    }
+
+    fn normal_at(&self, _world_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("normal_at should never be called on a Motion");
+    }
+
+    fn local_normal_at(&self, _local_point: Vec4, _i: &Intersection) -> Vec4 {
+        panic!("local_normal_at should never be called on a Motion");
+    }
+
+    fn transform(&self) -> &SqMatrix<4> {
+        &self.transform
+    }
+
+    fn material(&self) -> &Material {
+        self.material
+            .as_ref()
+            .unwrap_or_else(|| self.shape.material())
+    }
+
+    fn set_transformation(&mut self, mat: SqMatrix<4>) {
+        self.transform = mat;
+        self.inverse = mat.inverse();
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn inverse(&self) -> &Matrix<4, 4> {
+        &self.inverse
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::Sphere;
+    use crate::color::Color;
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let m = Motion::with_keyframes(
+            s,
+            vec![
+                (2.0, Matrix::translation(0.0, 0.0, 10.0)),
+                (0.0, Matrix::eye()),
+                (
+                    1.0,
+                    Matrix::translation(4.0, 0.0, 0.0) * Matrix::rotation_y(PI / 2.0),
+                ),
+            ],
+        );
+        let cases = [
+            (-1.0, Matrix::eye()),
+            (
+                0.5,
+                Matrix::translation(2.0, 0.0, 0.0) * Matrix::rotation_y(PI / 4.0),
+            ),
+            (
+                1.0,
+                Matrix::translation(4.0, 0.0, 0.0) * Matrix::rotation_y(PI / 2.0),
+            ),
+            (3.0, Matrix::translation(0.0, 0.0, 10.0)),
+        ];
+        for (time, expected) in cases {
+            assert_eq!(m.transform_at(time), expected);
+        }
+    }
+
+    #[test]
+    fn scale_and_rotation_are_interpolated_separately() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let start = Matrix::scaling(1.0, 2.0, 1.0);
+        let end = Matrix::rotation_z(PI / 2.0) * Matrix::scaling(3.0, 2.0, 1.0);
+        let m = Motion::new(s, start, end);
+        assert_eq!(m.transform_at(0.0), start);
+        assert_eq!(m.transform_at(1.0), end);
+        assert_eq!(
+            m.transform_at(0.5),
+            Matrix::rotation_z(PI / 4.0) * Matrix::scaling(2.0, 2.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn rays_hit_the_shape_where_it_is_at_their_time() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let m = Motion::new(s, Matrix::eye(), Matrix::translation(4.0, 0.0, 0.0));
+        assert_eq!(
+            m.bounds(),
+            Bounds::new(Vec4::point(-1.0, -1.0, -1.0), Vec4::point(5.0, 1.0, 1.0))
+        );
+
+        let r = Ray::new(2.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        assert!(m.intersect(&r).is_empty());
+        let r = r.at_time(0.5);
+        let xs = m.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(
+            xs[0].object.normal_at(r.position(4.0), &xs[0]),
+            Vec4::vector(0.0, 0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn material_overrides_the_moving_shape() {
+        let s: Arc<dyn Shape + Send + Sync> = Arc::new(Sphere::new());
+        let mut m = Motion::new(Arc::clone(&s), Matrix::eye(), Matrix::translation(4.0, 0.0, 0.0));
+        let mut material = Material::default();
+        material.color = Color::red();
+        m.set_material(material);
+
+        let xs = m.intersect(&Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0));
+        assert_eq!(xs[0].material().color, Color::red());
+        assert_eq!(s.material().color, Color::white());
+    }
+}
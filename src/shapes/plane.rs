@@ -37,7 +37,7 @@ impl Plane {
         let id = next_shape_id();
         Plane {
             id,
-            transform: mat.clone(),
+            transform: mat,
             material: Material::default(),
             inverse: mat.inverse(),
             bounds: Bounds::new(
@@ -79,7 +79,7 @@ impl Shape for Plane {
     }
 
     fn set_transformation(&mut self, mat: SqMatrix<4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
 
@@ -108,7 +108,7 @@ impl Shape for SmoothTriangle {
     }
 
     fn set_transformation(&mut self, mat: SqMatrix<4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
 
@@ -135,9 +135,12 @@ impl Shape for SmoothTriangle {
 
 #[cfg(test)]
 pub mod tests {
+    use std::f64::consts::PI;
     use std::sync::Arc;
 
     use super::SmoothTriangle;
+    use crate::matrix::Matrix;
+    use crate::shapes::group::Group;
     use crate::math::ApproxEq;
     use crate::{
         color::Color, intersection::Intersection, light::PointLight, ray::Ray, shapes::Shape,
@@ -157,7 +160,7 @@ pub mod tests {
         let tri = SmoothTriangle::new(p1, p2, p3, n1, n2, n3);
         let mut w = World::default();
         w.add_shape(Arc::new(tri));
-        let tri = w.shapes[2].as_ref();
+        let tri = w.shapes()[2].as_ref();
         let i = Intersection::new(3.5, tri, Some(0.2), Some(0.4));
         assert_eq!(i.u.unwrap(), 0.2);
         assert_eq!(i.v.unwrap(), 0.4);
@@ -197,7 +200,7 @@ pub mod tests {
 
         w.add_shape(Arc::new(tri));
 
-        let tri = w.shapes[0].as_ref();
+        let tri = w.shapes()[0].as_ref();
 
         let i = Intersection::new(1.0, tri, Some(u), Some(v));
         let n = tri.normal_at(Vec4::point(0.0, 0.0, 0.0), &i);
@@ -221,15 +224,45 @@ pub mod tests {
 
         w.add_shape(Arc::new(tri));
 
-        let tri = w.shapes[0].as_ref();
+        let tri = w.shapes()[0].as_ref();
 
         let i = Intersection::new(1.0, tri, Some(u), Some(v));
 
         let r = Ray::new(-0.2, 0.3, -2.0, 0.0, 0.0, 1.0);
         let xs = vec![i];
 
-        let comps = i.prepare_computations(&r, &xs);
+        let comps = xs[0].prepare_computations(&r, &xs);
 
         assert_eq!(comps.normalv, Vec4::vector(-0.5547, 0.83205, 0.0));
     }
+
+    #[test]
+    fn smooth_normal_in_transformed_group() {
+        let tri = || {
+            SmoothTriangle::new(
+                Vec4::point(0.0, 1.0, 0.0),
+                Vec4::point(-1.0, 0.0, 0.0),
+                Vec4::point(1.0, 0.0, 0.0),
+                Vec4::vector(0.0, 1.0, 0.0),
+                Vec4::vector(-1.0, 0.0, 0.0),
+                Vec4::vector(1.0, 0.0, 0.0),
+            )
+        };
+
+        let mut g = Group::new();
+        g.add_child(Arc::new(tri()));
+        g.set_transformation(Matrix::rotation_y(PI / 2.0));
+        let child = g.children()[0].as_ref();
+        let i = Intersection::new(1.0, child, Some(0.45), Some(0.25)).with_parent(&g.inverse);
+        let n = child.normal_at(Vec4::point(0.0, 0.0, 0.0), &i);
+        assert_eq!(n, Vec4::vector(0.0, 0.83205, 0.5547));
+
+        let mut g = Group::new();
+        g.add_child(Arc::new(tri()));
+        g.set_transformation(Matrix::scaling(2.0, 1.0, 1.0));
+        let child = g.children()[0].as_ref();
+        let i = Intersection::new(1.0, child, Some(0.45), Some(0.25)).with_parent(&g.inverse);
+        let n = child.normal_at(Vec4::point(0.0, 0.0, 0.0), &i);
+        assert_eq!(n, Vec4::vector(-0.5547 / 2.0, 0.83205, 0.0).norm());
+    }
 }
@@ -36,7 +36,7 @@ impl Sphere {
         let id = next_shape_id();
         Sphere {
             id,
-            transform: mat.clone(),
+            transform: mat,
             material: Material::default(),
             inverse: mat.inverse(),
             bounds: Sphere::get_bounds(),
@@ -88,7 +88,7 @@ impl Shape for Sphere {
     }
 
     fn set_transformation(&mut self, mat: Matrix<4, 4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
 
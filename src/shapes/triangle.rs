@@ -95,16 +95,12 @@ impl Shape for Triangle {
         &self.material
     }
 
-    fn normal_at(&self, _world_point: Vec4, _i: &Intersection) -> Vec4 {
-        self.normal
-    }
-
     fn set_material(&mut self, material: Material) {
         self.material = material;
     }
 
     fn set_transformation(&mut self, mat: SqMatrix<4>) {
-        self.transform = mat.clone();
+        self.transform = mat;
         self.inverse = mat.inverse();
     }
 
@@ -132,7 +128,13 @@ impl Shape for Triangle {
 #[cfg(test)]
 pub mod tests {
 
-    use crate::{ray::Ray, shapes::Shape, vec4::Vec4};
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    use crate::{
+        intersection::Intersection, matrix::Matrix, ray::Ray, shapes::Shape,
+        shapes::group::Group, vec4::Vec4,
+    };
 
     use super::Triangle;
 
@@ -181,4 +183,38 @@ pub mod tests {
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 2.0);
     }
+
+    #[test]
+    fn normal_in_rotated_group() {
+        let mut g = Group::new();
+        g.add_child(Arc::new(Triangle::new(
+            Vec4::point(0.0, 1.0, 0.0),
+            Vec4::point(-1.0, 0.0, 0.0),
+            Vec4::point(1.0, 0.0, 0.0),
+        )));
+        g.set_transformation(Matrix::rotation_y(PI / 2.0));
+
+        let r = Ray::new(-5.0, 0.5, 0.0, 1.0, 0.0, 0.0);
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 1);
+        let comps = xs[0].prepare_computations(&r, &xs);
+        assert_eq!(comps.normalv, Vec4::vector(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn normal_in_non_uniformly_scaled_group() {
+        let mut g = Group::new();
+        g.add_child(Arc::new(Triangle::new(
+            Vec4::point(1.0, 0.0, 0.0),
+            Vec4::point(0.0, 1.0, 0.0),
+            Vec4::point(0.0, 0.0, 1.0),
+        )));
+        g.set_transformation(Matrix::scaling(1.0, 2.0, 1.0));
+
+        let tri = g.children()[0].as_ref();
+        let i = Intersection::new(0.0, tri, None, None).with_parent(&g.inverse);
+        let n = tri.normal_at(Vec4::point(0.2, 1.0, 0.3), &i);
+        // normal of the plane through the scaled corners (1,0,0), (0,2,0), (0,0,1)
+        assert_eq!(n, Vec4::vector(-2.0, -1.0, -2.0).norm());
+    }
 }
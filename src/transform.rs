@@ -1,4 +1,5 @@
-use crate::matrix::SqMatrix;
+use crate::matrix::{Matrix, SqMatrix};
+use crate::vec4::Vec4;
 
 impl SqMatrix<4> {
     pub fn translation(x: f64, y: f64, z: f64) -> SqMatrix<4> {
@@ -53,6 +54,163 @@ impl SqMatrix<4> {
     }
 }
 
+/// A transform split into translation, rotation and scale, so that
+/// interpolating between two of them doesn't shear the shape.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decomposed {
+    translation: Vec4,
+    rotation: [f64; 4],
+    scale: SqMatrix<4>,
+}
+
+impl Decomposed {
+    pub(crate) fn new(mat: &SqMatrix<4>) -> Self {
+        let translation = Vec4::vector(mat[(0, 3)], mat[(1, 3)], mat[(2, 3)]);
+        let mut m = *mat;
+        for i in 0..3 {
+            m[(i, 3)] = 0.0;
+        }
+
+        // polar decomposition, average the matrix with its inverse transpose
+        // until only the rotation is left
+        let mut r = m;
+        for _ in 0..100 {
+            let next = (r + r.inverse().transpose()) * 0.5;
+            let converged =
+                (0..3).all(|i| (0..3).all(|j| (next[(i, j)] - r[(i, j)]).abs() < 1e-12));
+            r = next;
+            if converged {
+                break;
+            }
+        }
+        let scale = r.inverse() * m;
+        Decomposed {
+            translation,
+            rotation: quaternion_from_matrix(&r),
+            scale,
+        }
+    }
+
+    pub(crate) fn lerp(&self, other: &Decomposed, t: f64) -> Decomposed {
+        Decomposed {
+            translation: self.translation * (1.0 - t) + other.translation * t,
+            rotation: slerp(self.rotation, other.rotation, t),
+            scale: self.scale * (1.0 - t) + other.scale * t,
+        }
+    }
+
+    /// Catmull-Rom spline through `p1` and `p2` for translation and scale,
+    /// the rotation is slerped between the two.
+    pub(crate) fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f64) -> Decomposed {
+        let [w0, w1, w2, w3] = catmull_rom_weights(t);
+        Decomposed {
+            translation: p0.translation * w0
+                + p1.translation * w1
+                + p2.translation * w2
+                + p3.translation * w3,
+            rotation: slerp(p1.rotation, p2.rotation, t),
+            scale: p0.scale * w0 + p1.scale * w1 + p2.scale * w2 + p3.scale * w3,
+        }
+    }
+
+    pub(crate) fn matrix(&self) -> SqMatrix<4> {
+        let t = self.translation;
+        Matrix::translation(t.x, t.y, t.z) * matrix_from_quaternion(self.rotation) * self.scale
+    }
+}
+
+/// Weights of the four control points of a uniform Catmull-Rom spline at `t`
+/// between the second and the third point, they always add up to one.
+pub fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+fn quaternion_from_matrix(m: &SqMatrix<4>) -> [f64; 4] {
+    let trace = m[(0, 0)] + m[(1, 1)] + m[(2, 2)];
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [
+            s / 4.0,
+            (m[(2, 1)] - m[(1, 2)]) / s,
+            (m[(0, 2)] - m[(2, 0)]) / s,
+            (m[(1, 0)] - m[(0, 1)]) / s,
+        ]
+    } else if m[(0, 0)] > m[(1, 1)] && m[(0, 0)] > m[(2, 2)] {
+        let s = (1.0 + m[(0, 0)] - m[(1, 1)] - m[(2, 2)]).sqrt() * 2.0;
+        [
+            (m[(2, 1)] - m[(1, 2)]) / s,
+            s / 4.0,
+            (m[(0, 1)] + m[(1, 0)]) / s,
+            (m[(0, 2)] + m[(2, 0)]) / s,
+        ]
+    } else if m[(1, 1)] > m[(2, 2)] {
+        let s = (1.0 + m[(1, 1)] - m[(0, 0)] - m[(2, 2)]).sqrt() * 2.0;
+        [
+            (m[(0, 2)] - m[(2, 0)]) / s,
+            (m[(0, 1)] + m[(1, 0)]) / s,
+            s / 4.0,
+            (m[(1, 2)] + m[(2, 1)]) / s,
+        ]
+    } else {
+        let s = (1.0 + m[(2, 2)] - m[(0, 0)] - m[(1, 1)]).sqrt() * 2.0;
+        [
+            (m[(1, 0)] - m[(0, 1)]) / s,
+            (m[(0, 2)] + m[(2, 0)]) / s,
+            (m[(1, 2)] + m[(2, 1)]) / s,
+            s / 4.0,
+        ]
+    }
+}
+
+fn matrix_from_quaternion([w, x, y, z]: [f64; 4]) -> SqMatrix<4> {
+    Matrix::from_array([
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+            0.0,
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+            0.0,
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+fn slerp(a: [f64; 4], mut b: [f64; 4], t: f64) -> [f64; 4] {
+    let mut cos = (0..4).map(|i| a[i] * b[i]).sum::<f64>();
+    // take the short way around
+    if cos < 0.0 {
+        b = b.map(|c| -c);
+        cos = -cos;
+    }
+    let (wa, wb) = if cos > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let theta = cos.acos();
+        let sin = theta.sin();
+        (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+    };
+    let q: [f64; 4] = std::array::from_fn(|i| wa * a[i] + wb * b[i]);
+    let len = q.iter().map(|c| c * c).sum::<f64>().sqrt();
+    q.map(|c| c / len)
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::matrix::Matrix;
@@ -1,34 +1,44 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+use rand::RngCore;
 
 use crate::{
     Sphere,
+    bvh::Bvh,
     color::Color,
     intersection::{Computations, Intersection},
-    light::PointLight,
-    material::Material,
+    light::{Light, LightSample, PointLight},
+    material::{Material, sample_ggx},
     matrix::Matrix,
     ray::Ray,
-    shapes::Shape,
+    shapes::{Shape, group::Group},
     vec4::Vec4,
 };
 
 #[derive(Debug)]
 pub struct World {
-    pub light: PointLight,
-    pub shapes: Vec<Arc<dyn Shape + Send + Sync>>,
+    pub lights: Vec<Arc<dyn Light>>,
+    shapes: Vec<Arc<dyn Shape + Send + Sync>>,
+    // built on the first intersection after the shapes changed
+    bvh: OnceLock<Bvh>,
 }
 
 impl World {
-    pub fn new(light: PointLight) -> Self {
+    pub fn new(light: impl Light + 'static) -> Self {
         World {
-            light,
+            lights: vec![Arc::new(light)],
             shapes: Vec::new(),
+            bvh: OnceLock::new(),
         }
     }
     pub fn default() -> Self {
         let mut world = Self {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
+            lights: vec![Arc::new(PointLight::new(
+                Vec4::point(-10.0, 10.0, -10.0),
+                Color::white(),
+            ))],
             shapes: Vec::new(),
+            bvh: OnceLock::new(),
         };
 
         let mut s1 = Sphere::new();
@@ -52,110 +62,293 @@ impl World {
         world.add_shape(s2);
         world
     }
+    pub fn add_light(&mut self, light: impl Light + 'static) {
+        self.lights.push(Arc::new(light));
+    }
+    pub fn shapes(&self) -> &[Arc<dyn Shape + Send + Sync>] {
+        &self.shapes
+    }
+    /// Changing the shapes drops the BVH, the next intersection rebuilds it
+    /// once for the whole batch.
     pub fn add_shape(&mut self, shape: Arc<dyn Shape>) {
         self.shapes.push(shape);
+        self.bvh.take();
+    }
+    /// Puts `shape` in place of the shape at `index` and returns the old one.
+    pub fn replace_shape(&mut self, index: usize, shape: Arc<dyn Shape>) -> Arc<dyn Shape> {
+        self.bvh.take();
+        std::mem::replace(&mut self.shapes[index], shape) as Arc<dyn Shape>
     }
-    pub fn intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    /// Mutable access to the shape at `index`, which also drops the BVH.
+    pub fn shape_mut(&mut self, index: usize) -> &mut Arc<dyn Shape + Send + Sync> {
+        self.bvh.take();
+        &mut self.shapes[index]
+    }
+    pub fn remove_shape(&mut self, index: usize) -> Arc<dyn Shape> {
+        self.bvh.take();
+        self.shapes.remove(index)
+    }
+    pub fn clear_shapes(&mut self) {
+        self.bvh.take();
+        self.shapes.clear();
+    }
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let bounds = self
+                .shapes
+                .iter()
+                .map(|shape| Group::child_bounds(shape.as_ref()))
+                .collect::<Vec<_>>();
+            Bvh::build(&bounds)
+        })
+    }
+    pub fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         let mut xs: Vec<Intersection> = Vec::new();
-        for shape in &self.shapes {
-            xs.extend(shape.intersect(&ray));
-        }
+        self.bvh()
+            .traverse(ray, |i| xs.extend(self.shapes[i].intersect(ray)));
         xs.sort();
         xs
     }
-    pub fn shade_hit(&self, comps: Computations, remaining: usize) -> Color {
-        let in_shadow = self.is_shadowed(&comps.over_point);
-        let surface = Material::lighting(
-            comps.object().material(),
-            comps.object,
-            &self.light,
-            &comps.point,
-            &comps.eyev,
-            &comps.normalv,
-            in_shadow,
-        );
-        let reflected = self.reflected_color(&comps, remaining);
-        let refracted = self.refracted_color(&comps, remaining);
-
-        let mat = comps.object().material();
-        if mat.reflective > 0.0 && mat.transparency > 0.0 {
-            let reflectance = comps.schlick();
-            return surface + reflected * reflectance + refracted * (1.0 - reflectance);
+    /// Color of the hit described by `comps`, light samples and scattered
+    /// rays are drawn from `rng`.
+    pub fn shade_hit(&self, comps: Computations, remaining: usize, rng: &mut dyn RngCore) -> Color {
+        let mut surface = Color::black();
+        for light in &self.lights {
+            let samples =
+                self.visible_samples(light.as_ref(), &comps.over_point, comps.time, rng);
+            surface += Material::lighting(
+                comps.material,
+                &comps.object_point,
+                light.as_ref(),
+                &comps.eyev,
+                &comps.normalv,
+                &samples,
+            );
         }
+        let mat = comps.material;
+        let reflected = self.reflected_color(&comps, remaining, rng);
+        let refracted = self.refracted_color(&comps, remaining, rng);
 
-        surface + reflected + refracted
+        let color = if mat.reflective > 0.0 && mat.transparency > 0.0 {
+            let reflectance = comps.schlick();
+            surface + reflected * reflectance + refracted * (1.0 - reflectance)
+        } else {
+            surface + reflected + refracted
+        };
+        // absorbed on the way back through the medium the ray came through
+        color * comps.transmittance
     }
-    pub fn color_at(&self, ray: &Ray, remaining: usize) -> Color {
+    pub fn color_at(&self, ray: &Ray, remaining: usize, rng: &mut dyn RngCore) -> Color {
         let xs = self.intersect(ray);
         if let Some(hit) = Intersection::hit(&xs) {
             let comps = hit.prepare_computations(ray, &xs);
-            self.shade_hit(comps, remaining)
+            self.shade_hit(comps, remaining, rng)
         } else {
             Color::black()
         }
     }
-    pub fn is_shadowed(&self, point: &Vec4) -> bool {
-        let v = self.light.position - *point;
-        let dist = v.magnitude();
-        let dir = v.norm();
-
-        let r = Ray::from_vec4(*point, dir);
+    /// Share of the light that reaches `point`, averaged over the light's
+    /// samples. Transparent shapes in the way tint it rather than block it.
+    pub fn intensity_at(&self, light: &dyn Light, point: &Vec4, rng: &mut dyn RngCore) -> Color {
+        self.intensity_at_time(light, point, 0.0, rng)
+    }
+    /// Same as `intensity_at`, with shadow rays cast at `time`.
+    pub fn intensity_at_time(
+        &self,
+        light: &dyn Light,
+        point: &Vec4,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let samples = self.visible_samples(light, point, time, rng);
+        if samples.is_empty() {
+            return Color::black();
+        }
+        let mut sum = Color::black();
+        for (_, transmittance) in &samples {
+            sum += *transmittance;
+        }
+        sum / samples.len() as f64
+    }
+    /// Samples of `light` seen from `point`, each with the share of it that
+    /// gets past the shapes in between at `time`.
+    pub fn visible_samples(
+        &self,
+        light: &dyn Light,
+        point: &Vec4,
+        time: f64,
+        rng: &mut dyn RngCore,
+    ) -> Vec<(LightSample, Color)> {
+        light
+            .samples_at(point, rng)
+            .into_iter()
+            .map(|sample| {
+                let transmittance = self.shadow_transmittance(point, &sample, time);
+                (sample, transmittance)
+            })
+            .collect()
+    }
+    /// True if no part of the light can be seen from `point`.
+    pub fn is_shadowed(&self, light: &dyn Light, point: &Vec4, rng: &mut dyn RngCore) -> bool {
+        self.intensity_at(light, point, rng) == Color::black()
+    }
+    // Light let through along the shadow ray. Each pass through a shape lets
+    // `transparency` of it through and the way inside is absorbed by the
+    // material, also where the point or the light lie inside the shape.
+    // Shapes that don't cast shadows are ignored.
+    fn shadow_transmittance(&self, point: &Vec4, sample: &LightSample, time: f64) -> Color {
+        let r = Ray::from_vec4(*point, sample.lightv).at_time(time);
         let intersections = self.intersect(&r);
 
-        if let Some(h) = Intersection::hit(&intersections) {
-            if h.t < dist {
-                return true;
+        let mut transmittance = Color::white();
+        // shapes the ray is inside of and the `t` it entered them at
+        let mut inside: Vec<(&Intersection, f64)> = Vec::new();
+        for i in &intersections {
+            if i.t >= sample.distance && inside.is_empty() {
+                break;
+            }
+            let material = i.material();
+            if !material.casts_shadow {
+                continue;
+            }
+            let between = i.t > 0.0 && i.t < sample.distance;
+            let same = |(x, _): &(&Intersection, f64)| {
+                x.object.id() == i.object.id() && x.world_to_parent == i.world_to_parent
+            };
+            let entry = match inside.iter().position(same) {
+                Some(index) => Some(inside.remove(index).1),
+                None if i.t < sample.distance => {
+                    inside.push((i, i.t));
+                    None
+                }
+                // entering a shape behind the light
+                None => continue,
+            };
+            // a pass entered behind the point crosses its first surface here
+            if between && entry.is_none_or(|entry| entry <= 0.0) {
+                if material.transparency == 0.0 {
+                    return Color::black();
+                }
+                transmittance *= material.transparency;
+            }
+            if let Some(entry) = entry {
+                let path = i.t.min(sample.distance) - entry.max(0.0);
+                if path > 0.0 {
+                    transmittance *= material.transmittance(path);
+                }
             }
         }
-        false
+        transmittance
     }
-    pub fn reflected_color(&self, comps: &Computations, remaining: usize) -> Color {
-        let c = if comps.object.material().reflective == 0.0 || remaining <= 0 {
-            Color::black()
-        } else {
-            let reflect_ray = Ray::from_vec4(comps.over_point, comps.reflectv);
-            self.color_at(&reflect_ray, remaining - 1)
-        };
-        c * comps.object.material().reflective
+    pub fn reflected_color(
+        &self,
+        comps: &Computations,
+        remaining: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
+        let reflectance =
+            comps.material.reflectance(&comps.object_point, &comps.eyev, &comps.normalv);
+        if reflectance == Color::black() || remaining == 0 {
+            return Color::black();
+        }
+        let c = self.scattered_color(comps, comps.over_point, remaining, rng, |normal| {
+            let direction = (-comps.eyev).reflect(normal);
+            (direction.dot(&comps.normalv) > 0.0).then_some(direction)
+        });
+        c * reflectance
     }
-    pub fn refracted_color(&self, comps: &Computations, remaining: usize) -> Color {
+    pub fn refracted_color(
+        &self,
+        comps: &Computations,
+        remaining: usize,
+        rng: &mut dyn RngCore,
+    ) -> Color {
         if remaining == 0 {
             return Color::black();
         }
 
-        let transparency = comps.object.material().transparency;
+        let transparency = comps.material.transparency;
         if transparency == 0.0 {
             return Color::black();
         }
         let n_ratio = comps.n1 / comps.n2;
-        let cos_i = comps.eyev.dot(&comps.normalv);
-        let sin2_t = n_ratio.powi(2) * (1.0 - cos_i.powi(2));
-        if sin2_t > 1.0 {
-            return Color::black();
+        let c = self.scattered_color(comps, comps.under_point, remaining, rng, |normal| {
+            let cos_i = comps.eyev.dot(normal);
+            let sin2_t = n_ratio.powi(2) * (1.0 - cos_i.powi(2));
+            // total internal reflection
+            if sin2_t > 1.0 {
+                return None;
+            }
+            let cos_t = (1.0 - sin2_t).sqrt();
+            let direction = *normal * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
+            (direction.dot(&comps.normalv) < 0.0).then_some(direction)
+        });
+        c * transparency
+    }
+    // Color seen along `direction`, which bends the eye ray at a surface with
+    // the given normal. Rough materials scatter it over microfacet normals
+    // around the real one, directions ending up on the wrong side of the
+    // surface count as black. Only the first hit averages `glossy_samples`
+    // rays, deeper ones trace a single ray so the count doesn't grow
+    // exponentially with depth.
+    fn scattered_color<F>(
+        &self,
+        comps: &Computations,
+        origin: Vec4,
+        remaining: usize,
+        rng: &mut dyn RngCore,
+        direction: F,
+    ) -> Color
+    where
+        F: Fn(&Vec4) -> Option<Vec4>,
+    {
+        let trace = |normal: &Vec4, rng: &mut dyn RngCore| match direction(normal) {
+            Some(d) => {
+                let ray = Ray::from_vec4(origin, d)
+                    .at_time(comps.time)
+                    .with_wavelength(comps.wavelength)
+                    .with_bounces(comps.bounces + 1);
+                self.color_at(&ray, remaining - 1, rng)
+            }
+            None => Color::black(),
+        };
+        let mat = comps.material;
+        if mat.roughness == 0.0 {
+            return trace(&comps.normalv, rng);
         }
-        let cos_t = (1.0 - sin2_t).sqrt();
-        let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
-        let refract_ray = Ray::from_vec4(comps.under_point, direction);
-
-        self.color_at(&refract_ray, remaining - 1) * transparency
+        let samples = if comps.bounces == 0 {
+            mat.glossy_samples.max(1)
+        } else {
+            1
+        };
+        let mut sum = Color::black();
+        for _ in 0..samples {
+            let normal = sample_ggx(&comps.normalv, mat.roughness, rng);
+            sum += trace(&normal, rng);
+        }
+        sum / samples as f64
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use std::f64::consts::SQRT_2;
+    use std::f64::consts::{PI, SQRT_2};
     use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     use crate::Sphere;
     use crate::color::Color;
     use crate::intersection::Intersection;
-    use crate::light::PointLight;
+    use crate::light::{AreaLight, DirectionalLight, PointLight};
     use crate::material::Material;
     use crate::math::{ApproxEq, EPSILON};
     use crate::matrix::Matrix;
     use crate::patterns::TestPattern;
+    use crate::patterns::Pattern;
+    use crate::patterns::stripe_pattern::StripePattern;
     use crate::ray::Ray;
     use crate::shapes::Shape;
+    use crate::shapes::cube::Cube;
     use crate::shapes::plane::Plane;
     use crate::vec4::Vec4;
 
@@ -173,15 +366,32 @@ pub mod tests {
         assert_eq!(xs[3].t, 6.0);
     }
 
+    #[test]
+    fn changing_shapes_rebuilds_the_bvh() {
+        let mut w = World::default();
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        assert_eq!(w.intersect(&r).len(), 4);
+
+        w.add_shape(Arc::new(Sphere::with_transformation(Matrix::translation(0.0, 0.0, 5.0))));
+        assert_eq!(w.intersect(&r).len(), 6);
+        w.remove_shape(0);
+        assert_eq!(w.intersect(&r).len(), 4);
+        let far = Sphere::with_transformation(Matrix::translation(9.0, 0.0, 0.0));
+        w.replace_shape(0, Arc::new(far));
+        assert_eq!(w.intersect(&r).len(), 2);
+        w.clear_shapes();
+        assert!(w.intersect(&r).is_empty());
+    }
+
     #[test]
     fn shading_an_intersection() {
         let w = World::default();
         let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
 
-        let shape = &*w.shapes[0]; // first object
+        let shape = &*w.shapes()[0]; // first object
         let i = Intersection::new(4.0, shape, None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let c = w.shade_hit(comps, 0);
+        let c = w.shade_hit(comps, 0, &mut rand::rng());
 
         let expected = Color::new(0.38066, 0.47583, 0.2855);
         assert_eq!(c, expected);
@@ -190,13 +400,14 @@ pub mod tests {
     #[test]
     fn shading_an_intersection_from_inside() {
         let mut w = World::default();
-        w.light = PointLight::new(Vec4::point(0.0, 0.25, 0.0), Color::new(1.0, 1.0, 1.0));
+        w.lights.clear();
+        w.add_light(PointLight::new(Vec4::point(0.0, 0.25, 0.0), Color::new(1.0, 1.0, 1.0)));
 
         let r = Ray::new(0.0, 0.0, 0.0, 0.0, 0.0, 1.0);
-        let shape = &*w.shapes[1];
+        let shape = &*w.shapes()[1];
         let i = Intersection::new(0.5, shape, None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let c = w.shade_hit(comps, 0);
+        let c = w.shade_hit(comps, 0, &mut rand::rng());
 
         let expected = Color::new(0.90498, 0.90498, 0.90498);
         assert_eq!(c, expected);
@@ -205,14 +416,14 @@ pub mod tests {
     fn ray_miss() {
         let w = World::default();
         let r = Ray::new(0.0, 0.0, -5.0, 0.0, 1.0, 0.0);
-        let c = w.color_at(&r, 0);
+        let c = w.color_at(&r, 0, &mut rand::rng());
         assert_eq!(c, Color::black());
     }
     #[test]
     fn ray_hit() {
         let w = World::default();
         let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
-        let c = w.color_at(&r, 0);
+        let c = w.color_at(&r, 0, &mut rand::rng());
         assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
     }
 
@@ -220,21 +431,114 @@ pub mod tests {
     fn test_shading() {
         let w = World::default();
         let p = Vec4::point(-2.0, 2.0, -2.0);
-        assert_eq!(w.is_shadowed(&p), false);
+        assert_eq!(w.is_shadowed(w.lights[0].as_ref(), &p, &mut rand::rng()), false);
         let w = World::default();
         let p = Vec4::point(10.0, -10.0, 10.0);
-        assert_eq!(w.is_shadowed(&p), true);
+        assert_eq!(w.is_shadowed(w.lights[0].as_ref(), &p, &mut rand::rng()), true);
         let w = World::default();
         let p = Vec4::point(-20.0, 20.0, -20.0);
-        assert_eq!(w.is_shadowed(&p), false);
+        assert_eq!(w.is_shadowed(w.lights[0].as_ref(), &p, &mut rand::rng()), false);
+    }
+
+    #[test]
+    fn shade_hit_adds_up_all_lights() {
+        let w = World::default();
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let shape = &*w.shapes()[0];
+        let i = Intersection::new(4.0, shape, None, None);
+        let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
+        let single = w.shade_hit(comps, 0, &mut rand::rng());
+
+        let mut w = World::default();
+        w.add_light(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
+        let i = Intersection::new(4.0, &*w.shapes()[0], None, None);
+        let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
+        let double = w.shade_hit(comps, 0, &mut rand::rng());
+        assert_eq!(double, single * 2.0);
+    }
+
+    #[test]
+    fn shadows_are_tested_per_light() {
+        let mut w = World::default();
+        w.add_light(PointLight::new(Vec4::point(10.0, -10.0, 10.0), Color::white()));
+        let p = Vec4::point(-2.0, 2.0, -2.0);
+        assert_eq!(w.is_shadowed(w.lights[0].as_ref(), &p, &mut rand::rng()), false);
+        assert_eq!(w.is_shadowed(w.lights[1].as_ref(), &p, &mut rand::rng()), true);
+
+        // the point only sees the first light, the second one adds nothing but ambient
+        let mut shadowed = World::default();
+        shadowed.remove_shape(1);
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let lit = shadowed.color_at(&r, 0, &mut rand::rng());
+        shadowed.add_light(PointLight::new(Vec4::point(0.0, 0.0, 10.0), Color::white()));
+        let c = shadowed.color_at(&r, 0, &mut rand::rng());
+        assert_eq!(c, lit + Color::new(0.08, 0.1, 0.06));
+    }
+
+    #[test]
+    fn area_light_intensity_is_visible_fraction() {
+        let w = World::default();
+        let mut light = AreaLight::rect(
+            Vec4::point(-0.5, -0.5, -5.0),
+            Vec4::vector(1.0, 0.0, 0.0),
+            2,
+            Vec4::vector(0.0, 1.0, 0.0),
+            2,
+            Color::white(),
+        );
+        light.jitter = false;
+        let cases = [
+            (Vec4::point(0.0, 0.0, 2.0), 0.0),
+            (Vec4::point(1.0, -1.0, 2.0), 0.25),
+            (Vec4::point(1.5, 0.0, 2.0), 0.5),
+            (Vec4::point(1.25, 1.25, 3.0), 0.75),
+            (Vec4::point(0.0, 0.0, -2.0), 1.0),
+        ];
+        for (point, expected) in cases {
+            assert_eq!(w.intensity_at(&light, &point, &mut rand::rng()), Color::white() * expected);
+        }
+    }
+
+    #[test]
+    fn area_light_without_samples_gives_no_light() {
+        let mut w = World::default();
+        w.lights.clear();
+        let light = AreaLight::sphere(Vec4::point(-10.0, 10.0, -10.0), 0.5, 0, 2, Color::white());
+        let p = Vec4::point(0.0, 0.0, -2.0);
+        assert_eq!(w.intensity_at(&light, &p, &mut rand::rng()), Color::black());
+        w.add_light(light);
+
+        let c = w.color_at(&Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0), 0, &mut rand::rng());
+        assert_eq!(c, Color::new(0.08, 0.1, 0.06));
+    }
+
+    #[test]
+    fn shade_hit_with_area_light_only() {
+        let mut w = World::default();
+        w.lights.clear();
+        let mut light = AreaLight::sphere(Vec4::point(-10.0, 10.0, -10.0), 0.5, 2, 2, Color::white());
+        light.jitter = false;
+        w.add_light(light);
+
+        let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
+        let c = w.color_at(&r, 0, &mut rand::rng());
+        assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn directional_light_shadows_have_no_distance_limit() {
+        let mut w = World::default();
+        w.lights.clear();
+        w.add_light(DirectionalLight::new(Vec4::vector(0.0, -1.0, 0.0), Color::white()));
+        let light = w.lights[0].as_ref();
+        let mut rng = rand::rng();
+        assert!(w.is_shadowed(light, &Vec4::point(0.0, -1000.0, 0.0), &mut rng));
+        assert!(!w.is_shadowed(light, &Vec4::point(2.0, -1000.0, 0.0), &mut rng));
     }
 
     #[test]
     fn reflect_on_non_reflect() {
-        let mut world = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut world = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut mat1 = Material::default();
         mat1.set_color(Color {
@@ -268,20 +572,17 @@ pub mod tests {
         world.add_shape(s2);
 
         let r = Ray::new(0.0, 0.0, 0.0, 0.0, 0.0, 1.0);
-        let shape = &world.shapes[1];
+        let shape = &world.shapes()[1];
 
         let i = Intersection::new(1.0, shape.as_ref(), None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let color = world.reflected_color(&comps, 3);
+        let color = world.reflected_color(&comps, 3, &mut rand::rng());
         assert_eq!(color, Color::black());
     }
 
     #[test]
     fn reflected_color_for_a_reflective_material() {
-        let mut world = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut world = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut mat1 = Material::default();
         mat1.set_color(Color {
@@ -326,20 +627,217 @@ pub mod tests {
         world.add_shape(Arc::new(plane));
 
         let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
-        let shape = &world.shapes[2];
+        let shape = &world.shapes()[2];
 
         let i = Intersection::new(SQRT_2, shape.as_ref(), None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let color = world.reflected_color(&comps, 3);
+        let color = world.reflected_color(&comps, 3, &mut rand::rng());
         assert_eq!(color, Color::new(0.19032, 0.2379, 0.14274));
     }
 
     #[test]
-    fn shade_hit_with_reflective_material() {
-        let mut world = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
+    fn pbr_reflections_are_weighted_by_fresnel() {
+        let mut world = World::default();
+        let gold = Color::new(1.0, 0.8, 0.3);
+        let mut plane = Plane::new();
+        plane.set_transformation(Matrix::translation(0.0, -1.0, 0.0));
+        plane.set_material(Material::pbr(gold, 1.0, 0.0));
+        world.add_shape(Arc::new(plane));
+
+        let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
+        let i = Intersection::new(SQRT_2, world.shapes()[2].as_ref(), None, None);
+        let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
+        let light = world.lights[0].as_ref();
+        let surface = Material::lighting(
+            comps.material,
+            &comps.object_point,
+            light,
+            &comps.eyev,
+            &comps.normalv,
+            &world.visible_samples(light, &comps.over_point, 0.0, &mut rand::rng()),
+        );
+        // a smooth metal mirrors its surroundings without `reflective`
+        let mirror = Ray::from_vec4(comps.over_point, comps.reflectv);
+        let mirrored = world.color_at(&mirror, 2, &mut rand::rng());
+        let fresnel = crate::material::fresnel_schlick(gold, SQRT_2 / 2.0);
+        let reflected = world.reflected_color(&comps, 3, &mut rand::rng());
+        assert_ne!(mirrored, Color::black());
+        assert_eq!(reflected, mirrored * fresnel);
+        assert_eq!(world.shade_hit(comps, 3, &mut rand::rng()), surface + reflected);
+    }
+
+    // a plane at y = 0 under a sky that is white for x > 0 and black
+    // elsewhere, looked at straight down from just inside the white half
+    fn frosted_plane(roughness: f64) -> (World, Ray) {
+        let mut world = World::new(PointLight::new(Vec4::point(0.0, 50.0, 0.0), Color::white()));
+        world.clear_shapes();
+        let mut sky = Sphere::new();
+        sky.set_transformation(Matrix::scaling(100.0, 100.0, 100.0));
+        sky.material.ambient = 1.0;
+        sky.material.diffuse = 0.0;
+        sky.material.specular = 0.0;
+        sky.material.set_pattern(StripePattern::new(Color::white(), Color::black()));
+        world.add_shape(Arc::new(sky));
+
+        let mut plane = Plane::new();
+        let mut mat = Material::glas();
+        mat.ambient = 0.0;
+        mat.diffuse = 0.0;
+        mat.specular = 0.0;
+        mat.reflective = 1.0;
+        mat.roughness = roughness;
+        mat.glossy_samples = 64;
+        plane.set_material(mat);
+        world.add_shape(Arc::new(plane));
+        (world, Ray::new(0.5, 5.0, 0.0, 0.0, -1.0, 0.0))
+    }
+
+    #[test]
+    fn rough_surfaces_blur_reflection_and_refraction() {
+        for (roughness, sharp) in [(0.0, true), (1.0, false)] {
+            let (world, r) = frosted_plane(roughness);
+            let xs = world.intersect(&r);
+            let hit = xs
+                .iter()
+                .find(|i| i.object.id() == world.shapes()[1].id())
+                .unwrap();
+            let comps = hit.prepare_computations(&r, &xs);
+            for c in [
+                world.reflected_color(&comps, 3, &mut rand::rng()),
+                world.refracted_color(&comps, 3, &mut rand::rng()),
+            ] {
+                if sharp {
+                    assert_eq!(c, Color::white());
+                } else {
+                    // some rays scatter into the black half of the sky
+                    assert!(c.luminance() > 0.1 && c.luminance() < 0.9, "{c:?}");
+                }
+            }
+        }
+    }
+
+    // white everywhere, counts how often it is shaded
+    #[derive(Debug)]
+    struct CountingPattern(Arc<AtomicUsize>, Matrix<4, 4>);
+
+    impl Pattern for CountingPattern {
+        fn color_at(&self, _point: &Vec4) -> Color {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Color::white()
+        }
+        fn transform(&self) -> &Matrix<4, 4> {
+            &self.1
+        }
+        fn set_transformation(&mut self, _matrix: Matrix<4, 4>) {}
+        fn inverse(&self) -> &Matrix<4, 4> {
+            &self.1
+        }
+    }
+
+    #[test]
+    fn glossy_rays_do_not_multiply_with_depth() {
+        let shaded = Arc::new(AtomicUsize::new(0));
+        let mut world = World::new(PointLight::new(Vec4::point(0.0, 2.0, 0.0), Color::white()));
+        world.clear_shapes();
+        let mut mirror_box = Cube::new();
+        mirror_box.set_transformation(Matrix::scaling(5.0, 5.0, 5.0));
+        mirror_box.material.reflective = 1.0;
+        mirror_box.material.roughness = 0.3;
+        mirror_box
+            .material
+            .set_pattern(CountingPattern(Arc::clone(&shaded), Matrix::eye()));
+        world.add_shape(Arc::new(mirror_box));
+
+        let remaining = 4;
+        let r = Ray::new(0.0, 0.0, 0.0, 0.0, 0.0, 1.0);
+        world.color_at(&r, remaining, &mut rand::rng());
+        // the camera ray's hit and at most `glossy_samples` chains of single rays
+        let shaded = shaded.load(Ordering::Relaxed);
+        assert!(shaded > remaining && shaded <= 1 + 8 * remaining, "{shaded}");
+    }
+
+    #[test]
+    fn thick_glass_is_darker_than_thin_glass() {
+        let glass = |radius: f64| {
+            let mut world = World::new(PointLight::new(Vec4::point(0.0, 0.0, -10.0), Color::white()));
+            world.clear_shapes();
+            let mut backdrop = Plane::new();
+            backdrop.set_transformation(
+                Matrix::translation(0.0, 0.0, 10.0) * Matrix::rotation_x(PI / 2.0),
+            );
+            backdrop.material.ambient = 1.0;
+            world.add_shape(Arc::new(backdrop));
+
+            let mut ball = Sphere::glas(1.5);
+            ball.set_transformation(Matrix::scaling(radius, radius, radius));
+            ball.material.absorption = Color::new(0.5, 0.1, 0.5);
+            world.add_shape(Arc::new(ball));
+            world.color_at(&Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0), 5, &mut rand::rng())
         };
+        let (thin, thick) = (glass(0.5), glass(2.0));
+        assert!(thick.luminance() < thin.luminance());
+        // the green tint gets more saturated
+        assert!(thick.g / thick.r > thin.g / thin.r);
+    }
+
+    #[test]
+    fn transparent_shapes_cast_coloured_shadows() {
+        let mut w = World::new(PointLight::new(Vec4::point(0.0, 10.0, 0.0), Color::white()));
+        w.clear_shapes();
+        let mut pane = Sphere::glas(1.5);
+        pane.set_transformation(Matrix::translation(0.0, 5.0, 0.0));
+        pane.material.transparency = 0.8;
+        pane.material.absorption = Color::new(0.0, 1.0, 1.0);
+        w.add_shape(Arc::new(pane));
+        let light = w.lights[0].as_ref();
+        let p = Vec4::point(0.0, 0.0, 0.0);
+
+        // one pass through the pane and two units of red glass
+        let red = Color::new(0.8, 0.8 * (-2.0f64).exp(), 0.8 * (-2.0f64).exp());
+        assert_eq!(w.intensity_at(light, &p, &mut rand::rng()), red);
+        assert!(!w.is_shadowed(light, &p, &mut rand::rng()));
+
+        let mut opaque = Sphere::new();
+        opaque.set_transformation(Matrix::translation(0.0, 2.0, 0.0));
+        w.add_shape(Arc::new(opaque));
+        assert!(w.is_shadowed(w.lights[0].as_ref(), &p, &mut rand::rng()));
+
+        let mut ghost = Sphere::new();
+        ghost.set_transformation(Matrix::translation(0.0, 2.0, 0.0));
+        ghost.material.casts_shadow = false;
+        w.replace_shape(1, Arc::new(ghost));
+        assert_eq!(w.intensity_at(w.lights[0].as_ref(), &p, &mut rand::rng()), red);
+    }
+
+    #[test]
+    fn shadow_rays_starting_or_ending_inside_a_shape_are_absorbed() {
+        let mut w = World::new(PointLight::new(Vec4::point(0.0, 10.0, 0.0), Color::white()));
+        let mut ball = Sphere::glas(1.5);
+        ball.set_transformation(Matrix::scaling(2.0, 2.0, 2.0));
+        ball.material.transparency = 0.5;
+        ball.material.absorption = Color::new(0.0, 1.0, 1.0);
+        w.clear_shapes();
+        w.add_shape(Arc::new(ball));
+        let tinted = |depth: f64| Color::new(0.5, 0.5 * (-depth).exp(), 0.5 * (-depth).exp());
+
+        // from the centre out through the top, one surface and two units
+        let p = Vec4::point(0.0, 0.0, 0.0);
+        assert_eq!(w.intensity_at(w.lights[0].as_ref(), &p, &mut rand::rng()), tinted(2.0));
+
+        // from below to a light inside, one surface and three units
+        let light = PointLight::new(Vec4::point(0.0, 1.0, 0.0), Color::white());
+        let p = Vec4::point(0.0, -5.0, 0.0);
+        assert_eq!(w.intensity_at(&light, &p, &mut rand::rng()), tinted(3.0));
+
+        // both inside, nothing but absorption
+        let p = Vec4::point(0.0, -1.0, 0.0);
+        let absorbed = Color::new(1.0, (-2.0f64).exp(), (-2.0f64).exp());
+        assert_eq!(w.intensity_at(&light, &p, &mut rand::rng()), absorbed);
+    }
+
+    #[test]
+    fn shade_hit_with_reflective_material() {
+        let mut world = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut mat1 = Material::default();
         mat1.set_color(Color {
@@ -384,20 +882,17 @@ pub mod tests {
         world.add_shape(Arc::new(plane));
 
         let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
-        let shape = &world.shapes[2];
+        let shape = &world.shapes()[2];
 
         let i = Intersection::new(SQRT_2, shape.as_ref(), None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let color = world.shade_hit(comps, 1);
+        let color = world.shade_hit(comps, 1, &mut rand::rng());
         assert_eq!(color, Color::new(0.87677, 0.92436, 0.82918));
     }
 
     #[test]
     fn color_at_with_mutually_reflective_surfaces() {
-        let mut world = World {
-            light: PointLight::new(Vec4::point(0.0, 0.0, 0.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut world = World::new(PointLight::new(Vec4::point(0.0, 0.0, 0.0), Color::white()));
 
         let mut mat1 = Material::default();
         mat1.reflective = 1.0;
@@ -423,7 +918,7 @@ pub mod tests {
 
         let r = Ray::new(0.0, 0.0, 0.0, 0.0, 1.0, 0.0);
 
-        let _c = world.color_at(&r, 0);
+        let _c = world.color_at(&r, 0, &mut rand::rng());
     }
     #[test]
     fn color_at_max_recursion() {
@@ -441,11 +936,11 @@ pub mod tests {
         world.add_shape(Arc::new(plane));
 
         let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
-        let shape = &world.shapes[2];
+        let shape = &world.shapes()[2];
 
         let i = Intersection::new(SQRT_2, shape.as_ref(), None, None);
         let comps = i.prepare_computations(&r, &Vec::<Intersection>::new());
-        let color = world.reflected_color(&comps, 0);
+        let color = world.reflected_color(&comps, 0, &mut rand::rng());
         assert_eq!(color, Color::black());
     }
 
@@ -468,9 +963,9 @@ pub mod tests {
 
         let r = Ray::from_vec4(Vec4::point(0.0, 0.0, -4.0), Vec4::vector(0.0, 0.0, 1.0));
 
-        let a = &world.shapes[0];
-        let b = &world.shapes[1];
-        let c = &world.shapes[2];
+        let a = &world.shapes()[0];
+        let b = &world.shapes()[1];
+        let c = &world.shapes()[2];
         let xs = vec![
             Intersection::new(2.0, a.as_ref(), None, None),
             Intersection::new(2.75, b.as_ref(), None, None),
@@ -508,7 +1003,7 @@ pub mod tests {
 
         let r = Ray::from_vec4(Vec4::point(0.0, 0.0, -5.0), Vec4::vector(0.0, 0.0, 1.0));
 
-        let a = &world.shapes[0];
+        let a = &world.shapes()[0];
 
         let i = Intersection::new(5.0, a.as_ref(), None, None);
         let xs = world.intersect(&r);
@@ -520,7 +1015,7 @@ pub mod tests {
     #[test]
     fn transluscence_3() {
         let w = World::default();
-        let shape = &w.shapes[0];
+        let shape = &w.shapes()[0];
         let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
 
         let xs = vec![
@@ -528,15 +1023,12 @@ pub mod tests {
             Intersection::new(6.0, shape.as_ref(), None, None),
         ];
         let comps = xs[0].prepare_computations(&r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, &mut rand::rng());
         assert_eq!(c, Color::black());
     }
     #[test]
     fn transluscence_4() {
-        let mut w = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut w = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut s1 = Sphere::new();
         let mut mat1 = Material::default();
@@ -560,7 +1052,7 @@ pub mod tests {
         w.add_shape(s1);
         w.add_shape(s2);
 
-        let shape = &w.shapes[0];
+        let shape = &w.shapes()[0];
 
         let r = Ray::new(0.0, 0.0, -5.0, 0.0, 0.0, 1.0);
 
@@ -569,16 +1061,13 @@ pub mod tests {
             Intersection::new(6.0, shape.as_ref(), None, None),
         ];
         let comps = xs[0].prepare_computations(&r, &xs);
-        let c = w.refracted_color(&comps, 0);
+        let c = w.refracted_color(&comps, 0, &mut rand::rng());
         assert_eq!(c, Color::black());
     }
 
     #[test]
     fn transluscence_5() {
-        let mut w = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut w = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut s1 = Sphere::new();
         let mut mat1 = Material::default();
@@ -602,7 +1091,7 @@ pub mod tests {
         w.add_shape(s1);
         w.add_shape(s2);
 
-        let shape = &w.shapes[0];
+        let shape = &w.shapes()[0];
 
         let r = Ray::new(0.0, 0.0, SQRT_2 / 2.0, 0.0, 1.0, 0.0);
 
@@ -611,15 +1100,12 @@ pub mod tests {
             Intersection::new(SQRT_2 / 2.0, shape.as_ref(), None, None),
         ];
         let comps = xs[1].prepare_computations(&r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, &mut rand::rng());
         assert_eq!(c, Color::black());
     }
     #[test]
     fn transluscence_6() {
-        let mut w = World {
-            light: PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()),
-            shapes: Vec::new(),
-        };
+        let mut w = World::new(PointLight::new(Vec4::point(-10.0, 10.0, -10.0), Color::white()));
 
         let mut s1 = Sphere::new();
         let mut mat1 = Material::default();
@@ -646,8 +1132,8 @@ pub mod tests {
         w.add_shape(s1);
         w.add_shape(s2);
 
-        let a = &w.shapes[0];
-        let b = &w.shapes[1];
+        let a = &w.shapes()[0];
+        let b = &w.shapes()[1];
 
         let r = Ray::new(0.0, 0.0, 0.1, 0.0, 1.0, 0.0);
 
@@ -658,7 +1144,7 @@ pub mod tests {
             Intersection::new(0.9899, a.as_ref(), None, None),
         ];
         let comps = xs[2].prepare_computations(&r, &xs);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, &mut rand::rng());
         assert_eq!(c, Color::new(0.0, 0.9988, 0.04725));
     }
 
@@ -682,11 +1168,12 @@ pub mod tests {
         ball.set_transformation(Matrix::translation(0.0, -3.5, -0.5));
         w.add_shape(Arc::new(ball));
         let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
-        let floor = w.shapes[2].as_ref();
+        let floor = w.shapes()[2].as_ref();
         let xs = vec![Intersection::new(SQRT_2, floor, None, None)];
         let comps = xs[0].prepare_computations(&r, &xs);
-        let color = w.shade_hit(comps, 5);
-        assert_eq!(color, Color::new(0.93642, 0.68642, 0.68642));
+        let color = w.shade_hit(comps, 5, &mut rand::rng());
+        // half the light reaches the ball through the floor
+        assert_eq!(color, Color::new(1.12547, 0.68642, 0.68642));
     }
 
     #[test]
@@ -694,10 +1181,10 @@ pub mod tests {
         let shape = Sphere::glas(1.5);
         let r = Ray::new(0.0, 0.0, SQRT_2 / 2.0, 0.0, 1.0, 0.0);
         let mut w = World::default();
-        w.shapes.remove(0);
-        w.shapes.remove(0);
+        w.remove_shape(0);
+        w.remove_shape(0);
         w.add_shape(Arc::new(shape));
-        let shape = w.shapes[0].as_ref();
+        let shape = w.shapes()[0].as_ref();
         let xs = vec![
             Intersection::new(-SQRT_2 / 2.0, shape, None, None),
             Intersection::new(SQRT_2 / 2.0, shape, None, None),
@@ -711,10 +1198,10 @@ pub mod tests {
         let shape = Sphere::glas(1.5);
         let r = Ray::new(0.0, 0.0, 0.0, 0.0, 1.0, 0.0);
         let mut w = World::default();
-        w.shapes.remove(0);
-        w.shapes.remove(0);
+        w.remove_shape(0);
+        w.remove_shape(0);
         w.add_shape(Arc::new(shape));
-        let shape = w.shapes[0].as_ref();
+        let shape = w.shapes()[0].as_ref();
         let xs = vec![
             Intersection::new(-1.0, shape, None, None),
             Intersection::new(1.0, shape, None, None),
@@ -728,10 +1215,10 @@ pub mod tests {
         let shape = Sphere::glas(1.5);
         let r = Ray::new(0.0, 0.99, -2.0, 0.0, 0.0, 1.0);
         let mut w = World::default();
-        w.shapes.remove(0);
-        w.shapes.remove(0);
+        w.remove_shape(0);
+        w.remove_shape(0);
         w.add_shape(Arc::new(shape));
-        let shape = w.shapes[0].as_ref();
+        let shape = w.shapes()[0].as_ref();
         let xs = vec![Intersection::new(1.8589, shape, None, None)];
         let comps = xs[0].prepare_computations(&r, &xs);
         let reflectance = comps.schlick();
@@ -759,11 +1246,12 @@ pub mod tests {
         ball.set_transformation(Matrix::translation(0.0, -3.5, -0.5));
         w.add_shape(Arc::new(ball));
         let r = Ray::new(0.0, 0.0, -3.0, 0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0);
-        let floor = w.shapes[2].as_ref();
+        let floor = w.shapes()[2].as_ref();
         let xs = vec![Intersection::new(SQRT_2, floor, None, None)];
         let comps = xs[0].prepare_computations(&r, &xs);
-        let color = w.shade_hit(comps, 5);
-        assert!(color.r.approx_eq(&0.93391), "red channel off");
+        let color = w.shade_hit(comps, 5, &mut rand::rng());
+        // half the light reaches the ball through the floor
+        assert!(color.r.approx_eq(&1.11500), "red channel off");
         assert!(color.g.approx_eq(&0.69643), "green channel off");
         assert!(color.b.approx_eq(&0.69243), "blue channel off");
     }